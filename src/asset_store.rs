@@ -0,0 +1,121 @@
+use anyhow::{bail, Context, Result};
+use sdl2::{
+    image::LoadTexture,
+    render::{Texture, TextureCreator},
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ecs::components::AnimationClips;
+
+pub const DEFAULT_ASSETS_DIR: &str = "assets";
+
+// Textures and animation clips by id, kept as a Registry resource.
+// Paths are relative to the assets dir. Textures belong to the renderer that
+// created them and are freed with it.
+pub struct AssetStore {
+    root: PathBuf,
+    textures: HashMap<String, Texture>,
+    animations: HashMap<String, AnimationClips>,
+}
+
+impl AssetStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            textures: HashMap::new(),
+            animations: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // * Textures
+    // replaces any texture with the same id
+    pub fn add_texture<T>(
+        &mut self,
+        texture_creator: &TextureCreator<T>,
+        id: &str,
+        path: &Path,
+    ) -> Result<()> {
+        let path = self.root.join(path);
+        let texture = texture_creator
+            .load_texture(&path)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to load texture {}", path.display()))?;
+        self.remove_texture(id);
+        self.textures.insert(id.to_string(), texture);
+        Ok(())
+    }
+
+    pub fn has_texture(&self, id: &str) -> bool {
+        self.textures.contains_key(id)
+    }
+
+    pub fn get_texture(&self, id: &str) -> Option<&Texture> {
+        self.textures.get(id)
+    }
+
+    pub fn remove_texture(&mut self, id: &str) {
+        if let Some(texture) = self.textures.remove(id) {
+            // SAFETY: the renderer outlives the store's textures while the game runs
+            unsafe { texture.destroy() };
+        }
+    }
+
+    // * Animations
+    // Data file of clips grouped by animation id, eg
+    //
+    // [tank.idle]
+    // frame_width = 32
+    // frame_height = 32
+    // frame_count = 2
+    // frame_rate = 4.0
+    //
+    // [tank.fire]
+    // frame_width = 32
+    // frame_height = 32
+    // frame_count = 3
+    // frame_rate = 12.0
+    // row = 1
+    // looping = false
+    //
+    // Returns how many animations were loaded, existing ids are replaced.
+    pub fn load_animations(&mut self, path: &Path) -> Result<usize> {
+        let path = self.root.join(path);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read animations {}", path.display()))?;
+        let animations = parse_animations(&contents)
+            .with_context(|| format!("Failed to parse animations {}", path.display()))?;
+        let count = animations.len();
+        self.animations.extend(animations);
+        Ok(count)
+    }
+
+    pub fn add_animation(&mut self, id: &str, clips: AnimationClips) {
+        self.animations.insert(id.to_string(), clips);
+    }
+
+    // eg AnimationComponent::new(store.get_animation("tank")?.clone(), "idle")
+    pub fn get_animation(&self, id: &str) -> Option<&AnimationClips> {
+        self.animations.get(id)
+    }
+}
+
+pub fn parse_animations(contents: &str) -> Result<BTreeMap<String, AnimationClips>> {
+    let animations: BTreeMap<String, AnimationClips> = toml::from_str(contents)?;
+    for (id, clips) in &animations {
+        for (name, clip) in clips {
+            if clip.frame_width == 0 || clip.frame_height == 0 || clip.frame_count == 0 {
+                bail!("Clip {id}.{name} needs a non zero frame size and count");
+            }
+            if clip.frame_rate.is_nan() || clip.frame_rate < 0.0 {
+                bail!("Clip {id}.{name} frame_rate must be 0 or more");
+            }
+        }
+    }
+    Ok(animations)
+}
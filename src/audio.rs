@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use sdl2::{
+    mixer::{self, Channel, Chunk, InitFlag, Music, Sdl2MixerContext, AUDIO_S16LSB, MAX_VOLUME},
+    AudioSubsystem,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::event_bus::{EventBus, SubscriptionId};
+use crate::{kdbg, kinfo, kwarn};
+
+pub const DEFAULT_SFX_CHANNELS: i32 = 16;
+const SAMPLE_RATE: i32 = 44_100;
+const CHUNK_SIZE: i32 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VolumeGroup {
+    Master,
+    Music,
+    Sfx,
+}
+
+// 0.0..=1.0 per group, master scales the other two
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Volumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Volumes {
+    pub fn get(&self, group: VolumeGroup) -> f32 {
+        match group {
+            VolumeGroup::Master => self.master,
+            VolumeGroup::Music => self.music,
+            VolumeGroup::Sfx => self.sfx,
+        }
+    }
+
+    pub fn set(&mut self, group: VolumeGroup, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        match group {
+            VolumeGroup::Master => self.master = volume,
+            VolumeGroup::Music => self.music = volume,
+            VolumeGroup::Sfx => self.sfx = volume,
+        }
+    }
+
+    // what is heard, eg master 0.5 and sfx 0.5 plays sfx at 0.25
+    pub fn effective(&self, group: VolumeGroup) -> f32 {
+        match group {
+            VolumeGroup::Master => self.master,
+            group => self.master * self.get(group),
+        }
+    }
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+        }
+    }
+}
+
+// 0.0..=1.0 to SDL_mixer's 0..=MAX_VOLUME
+pub fn to_mix_volume(volume: f32) -> i32 {
+    (volume.clamp(0.0, 1.0) * MAX_VOLUME as f32).round() as i32
+}
+
+struct Mixer {
+    _context: Option<Sdl2MixerContext>, // quits SDL_mixer on drop
+    sounds: HashMap<String, Chunk>,
+    music: HashMap<String, Music<'static>>,
+}
+
+// Sound effects and music by id, kept as a Registry resource so systems can
+// play sounds. Without an audio device, eg the dummy driver when headless,
+// everything is a no-op and loads succeed without reading the files.
+// Paths are relative to the assets dir.
+pub struct AudioManager {
+    mixer: Option<Mixer>,
+    root: PathBuf,
+    volumes: Volumes,
+}
+
+impl AudioManager {
+    pub fn new(audio_subsystem: Option<&AudioSubsystem>, root: impl Into<PathBuf>) -> Self {
+        let mixer = audio_subsystem.and_then(|audio| {
+            let driver = audio.current_audio_driver();
+            if driver == "dummy" {
+                kinfo!("Audio off, dummy audio driver");
+                return None;
+            }
+            open_mixer().map_err(|e| kwarn!("Audio off: {:#}", e)).ok()
+        });
+        Self {
+            mixer,
+            root: root.into(),
+            volumes: Volumes::default(),
+        }
+    }
+
+    // a manager that never plays anything
+    pub fn disabled(root: impl Into<PathBuf>) -> Self {
+        Self::new(None, root)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mixer.is_some()
+    }
+
+    // * Loading
+    // replaces any sound with the same id
+    pub fn load_sound(&mut self, id: &str, path: &Path) -> Result<()> {
+        let path = self.root.join(path);
+        let Some(mixer) = self.mixer.as_mut() else {
+            kdbg!("Audio off, skipping sound {}", id);
+            return Ok(());
+        };
+        let chunk = Chunk::from_file(&path)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to load sound {}", path.display()))?;
+        mixer.sounds.insert(id.to_string(), chunk);
+        Ok(())
+    }
+
+    // streamed from disk while playing
+    pub fn load_music(&mut self, id: &str, path: &Path) -> Result<()> {
+        let path = self.root.join(path);
+        let Some(mixer) = self.mixer.as_mut() else {
+            kdbg!("Audio off, skipping music {}", id);
+            return Ok(());
+        };
+        let music = Music::from_file(&path)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to load music {}", path.display()))?;
+        mixer.music.insert(id.to_string(), music);
+        Ok(())
+    }
+
+    // * Playback
+    // On the first free sfx channel, a sound that can't play is logged and dropped
+    pub fn play_sound(&mut self, id: &str) {
+        let Some(mixer) = self.mixer.as_ref() else {
+            return;
+        };
+        let Some(chunk) = mixer.sounds.get(id) else {
+            kwarn!("Unknown sound {}", id);
+            return;
+        };
+        match Channel::all().play(chunk, 0) {
+            Ok(channel) => {
+                channel.set_volume(to_mix_volume(self.volumes.effective(VolumeGroup::Sfx)));
+            }
+            // eg every channel busy
+            Err(e) => kdbg!("Failed to play sound {}: {}", id, e),
+        }
+    }
+
+    pub fn stop_sounds(&mut self) {
+        if self.is_enabled() {
+            Channel::all().halt();
+        }
+    }
+
+    // loops -1 repeats forever, replaces the music that is playing
+    pub fn play_music(&mut self, id: &str, loops: i32) {
+        let Some(mixer) = self.mixer.as_ref() else {
+            return;
+        };
+        let Some(music) = mixer.music.get(id) else {
+            kwarn!("Unknown music {}", id);
+            return;
+        };
+        if let Err(e) = music.play(loops) {
+            kwarn!("Failed to play music {}: {}", id, e);
+        }
+        Music::set_volume(to_mix_volume(self.volumes.effective(VolumeGroup::Music)));
+    }
+
+    pub fn pause_music(&mut self) {
+        if self.is_enabled() {
+            Music::pause();
+        }
+    }
+
+    pub fn resume_music(&mut self) {
+        if self.is_enabled() {
+            Music::resume();
+        }
+    }
+
+    pub fn stop_music(&mut self) {
+        if self.is_enabled() {
+            Music::halt();
+        }
+    }
+
+    // * Volume
+    pub fn volumes(&self) -> Volumes {
+        self.volumes
+    }
+
+    pub fn volume(&self, group: VolumeGroup) -> f32 {
+        self.volumes.get(group)
+    }
+
+    // applies to what is already playing too
+    pub fn set_volume(&mut self, group: VolumeGroup, volume: f32) {
+        self.volumes.set(group, volume);
+        if !self.is_enabled() {
+            return;
+        }
+        Music::set_volume(to_mix_volume(self.volumes.effective(VolumeGroup::Music)));
+        Channel::all().set_volume(to_mix_volume(self.volumes.effective(VolumeGroup::Sfx)));
+    }
+}
+
+impl Drop for AudioManager {
+    fn drop(&mut self) {
+        // chunks and music must be freed before the device closes
+        if let Some(mixer) = self.mixer.take() {
+            Channel::all().halt();
+            Music::halt();
+            drop(mixer.sounds);
+            drop(mixer.music);
+            mixer::close_audio();
+        }
+    }
+}
+
+fn open_mixer() -> Result<Mixer> {
+    // WAV needs no decoder library, so missing OGG and MP3 support isn't fatal
+    let context = mixer::init(InitFlag::OGG | InitFlag::MP3)
+        .map_err(|e| kwarn!("No OGG or MP3 support: {}", e))
+        .ok();
+    mixer::open_audio(SAMPLE_RATE, AUDIO_S16LSB, 2, CHUNK_SIZE)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| "Failed to open audio device".to_owned())?;
+    mixer::allocate_channels(DEFAULT_SFX_CHANNELS);
+    Ok(Mixer {
+        _context: context,
+        sounds: HashMap::new(),
+        music: HashMap::new(),
+    })
+}
+
+// Plays sound_id whenever an E is emitted, eg
+// play_on_event::<CollisionEvent>(events, "hit")
+pub fn play_on_event<E: 'static>(events: &mut EventBus, sound_id: &str) -> SubscriptionId {
+    let sound_id = sound_id.to_string();
+    events.subscribe(move |_: &E, registry, _| {
+        if let Some(audio) = registry.get_resource_mut::<AudioManager>() {
+            audio.play_sound(&sound_id);
+        }
+    })
+}
@@ -0,0 +1,145 @@
+use nalgebra::Vector2;
+
+use crate::ecs::systems::world_transform;
+use crate::ecs::{Entity, Registry};
+
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 20.0;
+
+// World region the camera may show, eg the level's extent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraBounds {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+}
+
+// 2D view into the world, kept as a Registry resource.
+// Centered on `center`, zoom 2.0 shows everything twice as large.
+// Screen coordinates are window pixels with the origin top left, like SDL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    center: Vector2<f32>,
+    zoom: f32,
+    viewport: Vector2<f32>, // window size in pixels
+    bounds: Option<CameraBounds>,
+    target: Option<Entity>,
+}
+
+impl Camera {
+    // starts showing world (0, 0) at the top left, the same as raw screen coordinates
+    pub fn new(viewport_width: u32, viewport_height: u32) -> Self {
+        let viewport = Vector2::new(viewport_width as f32, viewport_height as f32);
+        Self {
+            center: viewport / 2.0,
+            zoom: 1.0,
+            viewport,
+            bounds: None,
+            target: None,
+        }
+    }
+
+    pub fn center(&self) -> Vector2<f32> {
+        self.center
+    }
+
+    pub fn set_center(&mut self, center: Vector2<f32>) {
+        self.center = center;
+        self.clamp_to_bounds();
+    }
+
+    pub fn pan(&mut self, delta: Vector2<f32>) {
+        self.set_center(self.center + delta);
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    // zooms about the center, clamped to MIN_ZOOM..=MAX_ZOOM
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.clamp_to_bounds();
+    }
+
+    pub fn viewport(&self) -> Vector2<f32> {
+        self.viewport
+    }
+
+    // eg when the window is resized
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        self.viewport = Vector2::new(width as f32, height as f32);
+        self.clamp_to_bounds();
+    }
+
+    pub fn bounds(&self) -> Option<CameraBounds> {
+        self.bounds
+    }
+
+    pub fn set_bounds(&mut self, bounds: Option<CameraBounds>) {
+        self.bounds = bounds;
+        self.clamp_to_bounds();
+    }
+
+    pub fn target(&self) -> Option<Entity> {
+        self.target
+    }
+
+    // centered on the entity's world position every frame, see update_camera
+    pub fn follow(&mut self, target: Option<Entity>) {
+        self.target = target;
+    }
+
+    pub fn world_to_screen(&self, world: Vector2<f32>) -> Vector2<f32> {
+        (world - self.center) * self.zoom + self.viewport / 2.0
+    }
+
+    // eg the world point under the mouse for picking
+    pub fn screen_to_world(&self, screen: Vector2<f32>) -> Vector2<f32> {
+        (screen - self.viewport / 2.0) / self.zoom + self.center
+    }
+
+    // world size of the visible area
+    pub fn view_size(&self) -> Vector2<f32> {
+        self.viewport / self.zoom
+    }
+
+    // (top left, bottom right) in world coordinates
+    pub fn visible_rect(&self) -> (Vector2<f32>, Vector2<f32>) {
+        let half = self.view_size() / 2.0;
+        (self.center - half, self.center + half)
+    }
+
+    // Keeps the view inside bounds, a view larger than bounds is centered on them
+    fn clamp_to_bounds(&mut self) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+        let half = self.view_size() / 2.0;
+        let clamp_axis = |center: f32, min: f32, max: f32, half: f32| {
+            if max - min <= half * 2.0 {
+                (min + max) / 2.0
+            } else {
+                center.clamp(min + half, max - half)
+            }
+        };
+        self.center = Vector2::new(
+            clamp_axis(self.center.x, bounds.min.x, bounds.max.x, half.x),
+            clamp_axis(self.center.y, bounds.min.y, bounds.max.y, half.y),
+        );
+    }
+}
+
+// Moves the Camera resource onto its follow target, once the frame's transforms are settled.
+// A target that no longer has a transform, eg killed, is dropped.
+pub fn update_camera(registry: &mut Registry) {
+    let Some(target) = registry.get_resource::<Camera>().and_then(Camera::target) else {
+        return;
+    };
+    let position = world_transform(registry, target).map(|transform| transform.position());
+    if let Some(camera) = registry.get_resource_mut::<Camera>() {
+        match position {
+            Some(position) => camera.set_center(position),
+            None => camera.follow(None),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+use crate::config::ConfigOverrides;
+use crate::logger::{parse_log_filter, parse_overflow_policy_value};
+
+pub const USAGE: &str = "\
+Usage: kengen [OPTIONS]
+
+Options:
+  -c, --config <FILE>        TOML config file [default: ./kengen.toml if present]
+      --width <PIXELS>       Window width
+      --height <PIXELS>      Window height
+      --fps <FRAMES>         Target framerate
+      --headless             Run without a window
+      --frames <FRAMES>      Stop a headless run after this many frames
+  -l, --loglevel <FILTER>    Log level, optionally per module
+                             eg: info,kengen::ecs=debug
+                             levels: debug, info, warning, error, critical
+  -o, --logoutput <FILE>     Log output file
+      --logasync <POLICY>    Log from a background thread, policy when full: drop, block
+  -h, --help                 Print this help and exit
+  -V, --version              Print version and exit
+
+Options override KENGEN_* env vars, which override the config file.";
+
+pub fn version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+// What the command line asked for. Help and version are returned rather than
+// printed so the caller decides how and where to print them.
+#[derive(Debug)]
+pub enum CliCommand {
+    Run(ConfigOverrides),
+    Help,
+    Version,
+}
+
+// `args` excludes the program name, eg: env::args().skip(1)
+pub fn parse_args<I>(args: I) -> Result<CliCommand>
+where
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    let mut args = args.into_iter().map(Into::into);
+    let mut overrides = ConfigOverrides::default();
+
+    while let Some(arg) = args.next() {
+        // accept both `--fps 30` and `--fps=30`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        // a value that looks like an option means the value was left out, eg `-o --headless`
+        let mut value = |name: &str| -> Result<String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .filter(|value| !value.starts_with("--"))
+                .ok_or_else(|| anyhow!("Missing argument for {name} ({flag})"))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-V" | "--version" => return Ok(CliCommand::Version),
+            "-c" | "--config" => {
+                overrides.config_path = Some(PathBuf::from(value("config file")?));
+            }
+            "--width" => {
+                overrides.window_width = Some(parse_positive(&value("window width")?, "--width")?);
+            }
+            "--height" => {
+                overrides.window_height =
+                    Some(parse_positive(&value("window height")?, "--height")?);
+            }
+            "--fps" => {
+                overrides.framerate = Some(parse_positive(&value("framerate")?, "--fps")?);
+            }
+            "--headless" => {
+                if inline_value.is_some() {
+                    return Err(anyhow!("--headless does not take a value"));
+                }
+                overrides.headless = Some(true);
+            }
+            "--frames" => {
+                overrides.headless_frames =
+                    Some(parse_positive(&value("frame count")?, "--frames")?.into());
+            }
+            "-l" | "--loglevel" => {
+                overrides.log_filter = Some(parse_log_filter(&value("log level")?)?);
+            }
+            "-o" | "--logoutput" => {
+                overrides.log_output = Some(value("log output")?);
+            }
+            "--logasync" => {
+                overrides.log_async =
+                    Some(parse_overflow_policy_value(&value("log overflow policy")?)?);
+            }
+            x if x.starts_with('-') => return Err(anyhow!("Unknown option: {x}")),
+            x => return Err(anyhow!("Unexpected argument: {x}")),
+        }
+    }
+    Ok(CliCommand::Run(overrides))
+}
+
+fn parse_positive(value: &str, flag: &str) -> Result<u32> {
+    let n: u32 = value
+        .parse()
+        .with_context(|| format!("Malformed value for {flag}: {value}"))?;
+    if n == 0 {
+        return Err(anyhow!("Value for {flag} must be greater than 0"));
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::OverflowPolicy;
+
+    fn run(args: &[&str]) -> Result<ConfigOverrides> {
+        match parse_args(args.iter().copied())? {
+            CliCommand::Run(overrides) => Ok(overrides),
+            command => Err(anyhow!("Expected a run, got {command:?}")),
+        }
+    }
+
+    #[test]
+    fn no_args_override_nothing() {
+        let overrides = run(&[]).unwrap();
+        assert_eq!(overrides.framerate, None);
+        assert_eq!(overrides.headless, None);
+        assert_eq!(overrides.log_output, None);
+    }
+
+    #[test]
+    fn values_can_be_inline_or_the_next_arg() {
+        assert_eq!(run(&["--fps=30"]).unwrap().framerate, Some(30));
+        assert_eq!(run(&["--fps", "30"]).unwrap().framerate, Some(30));
+
+        let overrides = run(&[
+            "--width=1024",
+            "--height",
+            "768",
+            "--headless",
+            "--frames=10",
+            "-o",
+            "kengen.log",
+            "-c",
+            "other.toml",
+            "--logasync=drop",
+        ])
+        .unwrap();
+        assert_eq!(overrides.window_width, Some(1024));
+        assert_eq!(overrides.window_height, Some(768));
+        assert_eq!(overrides.headless, Some(true));
+        assert_eq!(overrides.headless_frames, Some(10));
+        assert_eq!(overrides.log_output.as_deref(), Some("kengen.log"));
+        assert_eq!(overrides.config_path, Some(PathBuf::from("other.toml")));
+        assert_eq!(overrides.log_async, Some(OverflowPolicy::Drop));
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        assert!(run(&["--fps"]).is_err());
+        assert!(run(&["-o"]).is_err());
+        assert!(run(&["-o", "--headless"]).is_err());
+        assert!(run(&["--loglevel", "--fps=30"]).is_err());
+    }
+
+    #[test]
+    fn sizes_and_counts_must_be_positive() {
+        assert!(run(&["--fps", "0"]).is_err());
+        assert!(run(&["--fps", "-30"]).is_err());
+        assert!(run(&["--width=0"]).is_err());
+        assert!(run(&["--frames", "many"]).is_err());
+    }
+
+    #[test]
+    fn unknown_options_and_positional_args_are_errors() {
+        assert!(run(&["--fullscreen"]).is_err());
+        assert!(run(&["-x"]).is_err());
+        assert!(run(&["game.toml"]).is_err());
+        assert!(run(&["--headless=yes"]).is_err());
+    }
+
+    #[test]
+    fn help_and_version_stop_parsing() {
+        assert!(matches!(parse_args(["-h"]), Ok(CliCommand::Help)));
+        assert!(matches!(
+            parse_args(["--help", "--bogus"]),
+            Ok(CliCommand::Help)
+        ));
+        assert!(matches!(parse_args(["-V"]), Ok(CliCommand::Version)));
+        assert!(matches!(
+            parse_args(["--fps", "30", "--version", "extra"]),
+            Ok(CliCommand::Version)
+        ));
+    }
+}
@@ -0,0 +1,471 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::input::gamepad::DEFAULT_DEADZONE;
+use crate::input::{default_bindings, parse_input_binding, InputBindings};
+use crate::logger::{
+    parse_log_filter, parse_overflow_policy_value, LogFilter, LogLevel, OverflowPolicy,
+    LOG_FILTER_ENV_VAR,
+};
+
+// Read from the working directory when no config path is given, fine if missing
+pub const DEFAULT_CONFIG_PATH: &str = "kengen.toml";
+pub const CONFIG_PATH_ENV_VAR: &str = "KENGEN_CONFIG";
+
+// Settings the engine is started with. Built up in layers, each overriding the last:
+// defaults < TOML config file < KENGEN_* env vars < command line
+#[derive(Clone, Debug)]
+pub struct GameConfiguration {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub window_title: String,
+    pub framerate: u32,
+    pub vsync: bool,
+    pub borderless: bool,
+    pub headless: bool,
+    pub headless_frames: Option<u64>, // None runs until quit
+    pub log_filter: LogFilter,
+    pub log_output: Option<String>,
+    pub log_async: Option<OverflowPolicy>,
+    pub input_bindings: InputBindings,
+    pub gamepad_deadzone: f32, // 0.0..1.0 of stick travel
+}
+
+impl Default for GameConfiguration {
+    fn default() -> Self {
+        Self {
+            window_width: 800,
+            window_height: 600,
+            window_title: "kengen".to_string(),
+            framerate: 60,
+            vsync: false,
+            borderless: true,
+            headless: false,
+            headless_frames: None,
+            log_filter: LogFilter::new(LogLevel::Debug),
+            log_output: None,
+            log_async: None,
+            input_bindings: default_bindings(),
+            gamepad_deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+// One layer of configuration, None means "leave whatever the lower layer set"
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOverrides {
+    pub config_path: Option<PathBuf>,
+    pub window_width: Option<u32>,
+    pub window_height: Option<u32>,
+    pub window_title: Option<String>,
+    pub framerate: Option<u32>,
+    pub vsync: Option<bool>,
+    pub borderless: Option<bool>,
+    pub headless: Option<bool>,
+    pub headless_frames: Option<u64>,
+    pub log_filter: Option<LogFilter>,
+    pub log_output: Option<String>,
+    pub log_async: Option<OverflowPolicy>,
+    pub input_bindings: Option<InputBindings>, // replaces bindings per action
+    pub gamepad_deadzone: Option<f32>,
+}
+
+impl GameConfiguration {
+    // Full layered load: the config file is picked from the command line, then
+    // KENGEN_CONFIG, then ./kengen.toml if it exists
+    pub fn load(cli: ConfigOverrides) -> Result<Self> {
+        let env = ConfigOverrides::from_env(|key| env::var(key).ok())?;
+
+        let mut config = Self::default();
+        match cli.config_path.as_ref().or(env.config_path.as_ref()) {
+            Some(path) => config.apply(ConfigOverrides::from_file(path)?),
+            None => {
+                let default_path = Path::new(DEFAULT_CONFIG_PATH);
+                if default_path.exists() {
+                    config.apply(ConfigOverrides::from_file(default_path)?);
+                }
+            }
+        }
+        config.apply(env);
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply(&mut self, overrides: ConfigOverrides) {
+        let ConfigOverrides {
+            config_path: _,
+            window_width,
+            window_height,
+            window_title,
+            framerate,
+            vsync,
+            borderless,
+            headless,
+            headless_frames,
+            log_filter,
+            log_output,
+            log_async,
+            input_bindings,
+            gamepad_deadzone,
+        } = overrides;
+
+        if let Some(x) = window_width {
+            self.window_width = x;
+        }
+        if let Some(x) = window_height {
+            self.window_height = x;
+        }
+        if let Some(x) = window_title {
+            self.window_title = x;
+        }
+        if let Some(x) = framerate {
+            self.framerate = x;
+        }
+        if let Some(x) = vsync {
+            self.vsync = x;
+        }
+        if let Some(x) = borderless {
+            self.borderless = x;
+        }
+        if let Some(x) = headless {
+            self.headless = x;
+        }
+        if let Some(x) = headless_frames {
+            self.headless_frames = Some(x);
+        }
+        if let Some(x) = log_filter {
+            self.log_filter = x;
+        }
+        if let Some(x) = log_output {
+            self.log_output = Some(x);
+        }
+        if let Some(x) = log_async {
+            self.log_async = Some(x);
+        }
+        if let Some(x) = input_bindings {
+            self.input_bindings.extend(x);
+        }
+        if let Some(x) = gamepad_deadzone {
+            self.gamepad_deadzone = x;
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.window_width == 0 || self.window_height == 0 {
+            return Err(anyhow!(
+                "Window size must be non-zero, got {}x{}",
+                self.window_width,
+                self.window_height
+            ));
+        }
+        if self.framerate == 0 {
+            return Err(anyhow!("Framerate must be non-zero"));
+        }
+        if !(0.0..1.0).contains(&self.gamepad_deadzone) {
+            return Err(anyhow!(
+                "Gamepad deadzone must be in 0.0..1.0, got {}",
+                self.gamepad_deadzone
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn frame_limit_ms(&self) -> f64 {
+        1000.0 / self.framerate as f64
+    }
+}
+
+// eg kengen.toml:
+//
+// framerate = 60
+// headless = false
+// headless_frames = 600
+//
+// [window]
+// width = 800
+// height = 600
+// title = "kengen"
+// vsync = false
+// borderless = true
+//
+// [log]
+// level = "info,kengen::ecs=debug"
+// output = "kengen.log"
+// async = "drop"
+//
+// [input]
+// pause = ["P", "Pad:start"]
+// fire = ["Space", "Mouse:Left", "Pad:a"]
+//
+// [gamepad]
+// deadzone = 0.15
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    framerate: Option<u32>,
+    headless: Option<bool>,
+    headless_frames: Option<u64>,
+    #[serde(default)]
+    window: WindowSection,
+    #[serde(default)]
+    log: LogSection,
+    #[serde(default)]
+    input: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    gamepad: GamepadSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct WindowSection {
+    width: Option<u32>,
+    height: Option<u32>,
+    title: Option<String>,
+    vsync: Option<bool>,
+    borderless: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct LogSection {
+    level: Option<String>,
+    output: Option<String>,
+    #[serde(rename = "async")]
+    async_policy: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct GamepadSection {
+    deadzone: Option<f32>,
+}
+
+impl ConfigOverrides {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(contents)?;
+        let input_bindings = file
+            .input
+            .iter()
+            .map(|(action, bindings)| {
+                let bindings = bindings
+                    .iter()
+                    .map(|binding| parse_input_binding(binding))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((action.clone(), bindings))
+            })
+            .collect::<Result<InputBindings>>()?;
+        Ok(Self {
+            config_path: None,
+            window_width: file.window.width,
+            window_height: file.window.height,
+            window_title: file.window.title,
+            framerate: file.framerate,
+            vsync: file.window.vsync,
+            borderless: file.window.borderless,
+            headless: file.headless,
+            headless_frames: file.headless_frames,
+            log_filter: file
+                .log
+                .level
+                .as_deref()
+                .map(parse_log_filter)
+                .transpose()?,
+            log_output: file.log.output,
+            log_async: file
+                .log
+                .async_policy
+                .as_deref()
+                .map(parse_overflow_policy_value)
+                .transpose()?,
+            input_bindings: Some(input_bindings).filter(|bindings| !bindings.is_empty()),
+            gamepad_deadzone: file.gamepad.deadzone,
+        })
+    }
+
+    // `lookup` is env::var in practice, taken as a param so other sources can stand in
+    pub fn from_env(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        Ok(Self {
+            config_path: lookup(CONFIG_PATH_ENV_VAR).map(PathBuf::from),
+            window_width: parse_env(&lookup, "KENGEN_WINDOW_WIDTH")?,
+            window_height: parse_env(&lookup, "KENGEN_WINDOW_HEIGHT")?,
+            window_title: lookup("KENGEN_WINDOW_TITLE"),
+            framerate: parse_env(&lookup, "KENGEN_FRAMERATE")?,
+            vsync: parse_env(&lookup, "KENGEN_VSYNC")?,
+            borderless: parse_env(&lookup, "KENGEN_BORDERLESS")?,
+            headless: parse_env(&lookup, "KENGEN_HEADLESS")?,
+            headless_frames: parse_env(&lookup, "KENGEN_HEADLESS_FRAMES")?,
+            log_filter: lookup(LOG_FILTER_ENV_VAR)
+                .as_deref()
+                .map(parse_log_filter)
+                .transpose()?,
+            log_output: lookup("KENGEN_LOG_OUTPUT"),
+            log_async: lookup("KENGEN_LOG_ASYNC")
+                .as_deref()
+                .map(parse_overflow_policy_value)
+                .transpose()?,
+            input_bindings: None,
+            gamepad_deadzone: parse_env(&lookup, "KENGEN_GAMEPAD_DEADZONE")?,
+        })
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(
+    lookup: &impl Fn(&str) -> Option<String>,
+    key: &str,
+) -> Result<Option<T>> {
+    match lookup(key) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("Malformed value for {key}: {value}")),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::actions;
+    use crate::input::InputBinding;
+    use sdl2::keyboard::Keycode;
+    use sdl2::mouse::MouseButton;
+    use std::collections::HashMap;
+
+    fn fake_env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn layers_override_in_order() {
+        let mut config = GameConfiguration::default();
+        config.apply(
+            ConfigOverrides::from_toml(
+                "framerate = 30\n\
+                 [window]\n\
+                 width = 1024\n\
+                 height = 768\n\
+                 title = \"from file\"\n",
+            )
+            .unwrap(),
+        );
+        config.apply(
+            ConfigOverrides::from_env(fake_env(&[
+                ("KENGEN_FRAMERATE", "120"),
+                ("KENGEN_WINDOW_WIDTH", "1280"),
+            ]))
+            .unwrap(),
+        );
+        config.apply(ConfigOverrides {
+            framerate: Some(144),
+            ..Default::default()
+        });
+
+        assert_eq!(config.framerate, 144); // cli
+        assert_eq!(config.window_width, 1280); // env
+        assert_eq!(config.window_height, 768); // file
+        assert_eq!(config.window_title, "from file");
+        assert!(config.borderless); // default
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn empty_layers_change_nothing() {
+        let mut config = GameConfiguration::default();
+        config.apply(ConfigOverrides::from_toml("").unwrap());
+        config.apply(ConfigOverrides::from_env(fake_env(&[])).unwrap());
+        config.apply(ConfigOverrides::default());
+
+        let default = GameConfiguration::default();
+        assert_eq!(config.window_width, default.window_width);
+        assert_eq!(config.framerate, default.framerate);
+        assert_eq!(config.log_output, None);
+        assert_eq!(config.input_bindings, default.input_bindings);
+    }
+
+    #[test]
+    fn env_reads_log_settings_and_config_path() {
+        let env = ConfigOverrides::from_env(fake_env(&[
+            (CONFIG_PATH_ENV_VAR, "other.toml"),
+            (LOG_FILTER_ENV_VAR, "warning"),
+            ("KENGEN_LOG_OUTPUT", "kengen.log"),
+            ("KENGEN_LOG_ASYNC", "block"),
+            ("KENGEN_HEADLESS", " true "),
+        ]))
+        .unwrap();
+
+        assert_eq!(env.config_path, Some(PathBuf::from("other.toml")));
+        assert_eq!(
+            env.log_filter.map(|filter| filter.default_level()),
+            Some(LogLevel::Warning)
+        );
+        assert_eq!(env.log_output.as_deref(), Some("kengen.log"));
+        assert_eq!(env.log_async, Some(OverflowPolicy::Block));
+        assert_eq!(env.headless, Some(true));
+    }
+
+    #[test]
+    fn malformed_env_values_are_errors() {
+        assert!(ConfigOverrides::from_env(fake_env(&[("KENGEN_FRAMERATE", "fast")])).is_err());
+        assert!(ConfigOverrides::from_env(fake_env(&[("KENGEN_VSYNC", "yes")])).is_err());
+        assert!(ConfigOverrides::from_env(fake_env(&[("KENGEN_LOG_ASYNC", "maybe")])).is_err());
+    }
+
+    #[test]
+    fn unknown_file_keys_are_rejected() {
+        assert!(ConfigOverrides::from_toml("fps = 30").is_err());
+        assert!(ConfigOverrides::from_toml("[window]\nwidht = 800").is_err());
+        assert!(ConfigOverrides::from_toml("[log]\nlevel = \"info\"\ncolor = true").is_err());
+        assert!(ConfigOverrides::from_toml("[sound]\nvolume = 1.0").is_err());
+    }
+
+    #[test]
+    fn file_bindings_replace_only_the_actions_they_name() {
+        let mut config = GameConfiguration::default();
+        config.apply(
+            ConfigOverrides::from_toml(
+                "[input]\npause = [\"Mouse:Right\"]\nfire = [\"Mouse:Left\"]",
+            )
+            .unwrap(),
+        );
+
+        let bindings = &config.input_bindings;
+        assert_eq!(
+            bindings[actions::PAUSE],
+            [InputBinding::Mouse(MouseButton::Right)]
+        );
+        assert_eq!(bindings["fire"], [InputBinding::Mouse(MouseButton::Left)]);
+        assert_eq!(
+            bindings[actions::STOP],
+            [InputBinding::Key(Keycode::Escape)]
+        );
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_values() {
+        let mut config = GameConfiguration::default();
+        config.apply(ConfigOverrides::from_toml("[gamepad]\ndeadzone = 1.0").unwrap());
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfiguration::default();
+        config
+            .apply(ConfigOverrides::from_env(fake_env(&[("KENGEN_WINDOW_HEIGHT", "0")])).unwrap());
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,25 +1,51 @@
 #[allow(warnings, dead_code)]
 use std::collections::VecDeque;
 
-// TODO use traits to cover any from u8 to u64 depending on limit
-pub struct FixedSizeQueue {
-    queue: VecDeque<u64>,
+pub struct FixedSizeQueue<T = u64> {
+    queue: VecDeque<T>,
     limit: usize,
 }
 
-impl FixedSizeQueue {
+impl<T> FixedSizeQueue<T> {
     pub fn new(limit: usize) -> Self {
         Self {
-            queue: VecDeque::new(),
+            queue: VecDeque::with_capacity(limit),
             limit,
         }
     }
-    pub fn push(&mut self, value: u64) {
+    pub fn push(&mut self, value: T) {
         if self.queue.len() >= self.limit {
             self.queue.pop_front();
         }
         self.queue.push_back(value);
     }
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+    // shrinking drops the oldest values
+    pub fn set_limit(&mut self, limit: usize) {
+        while self.queue.len() > limit {
+            self.queue.pop_front();
+        }
+        self.limit = limit;
+    }
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+    // oldest to newest
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, T> {
+        self.queue.iter()
+    }
+}
+
+// TODO use traits to cover any from u8 to u64 depending on limit
+impl FixedSizeQueue<u64> {
     pub fn avg(&self) -> Option<f64> {
         if self.queue.is_empty() {
             return None;
@@ -32,6 +58,7 @@ impl FixedSizeQueue {
 // TODO generics/trait bounds to cover any size from u8 u32 u64
 // TODO tests
 // TODO read C++ implementation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct BitSet {
     data: u32,
 }
@@ -1,17 +1,17 @@
-#[allow(warnings, dead_code)]
-use crate::dsa::{BitSet, FixedSizeQueue};
-use std::any::TypeId;
+use crate::dsa::BitSet;
+use crate::ecs::components::{Component, ParentComponent};
+use crate::event_bus::EventBus;
+use std::any::{Any, TypeId};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::{Index, IndexMut};
-use std::rc::Rc;
-use crate::ecs::components::Component;
+use std::time::{Duration, Instant};
 
 use crate::ecs::components::NewFromArgs;
 
 pub mod components;
 pub mod systems;
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Entity {
     id: usize,
 }
@@ -25,19 +25,18 @@ impl Entity {
     pub fn get_id(&self) -> usize {
         self.id
     }
+}
 
-    // ? move to registry?
-    // pub fn add_component<TComponent>( ...args) {}
-    // pub fn remove_component<TComponent>() {}
-    // pub fn has_component<TComponent>() -> bool {}
-    // pub fn get_component<TComponent>() -> TComponent {}
+// Type erased pool so the registry can hold pools of any component type
+trait IPool: Any {
+    fn remove_entity_from_pool(&mut self, entity_id: usize);
 }
 
-// trait bound to component types
+// Densely packed components, removal swaps the last component into the gap
 struct Pool<TComponent> {
     components: Vec<TComponent>,
-    entity_id_to_index: HashMap<u32, usize>,
-    index_to_entity_id: HashMap<usize, u32>,
+    entity_id_to_index: HashMap<usize, usize>,
+    index_to_entity_id: HashMap<usize, usize>,
 }
 
 impl<TComponent> Pool<TComponent> {
@@ -49,20 +48,51 @@ impl<TComponent> Pool<TComponent> {
             index_to_entity_id: HashMap::new(),
         }
     }
-    pub fn is_empty(&self) -> bool {
-        self.components.is_empty()
+    // replaces the entity's component if it already has one
+    pub fn set(&mut self, entity_id: usize, component: TComponent) {
+        if let Some(&index) = self.entity_id_to_index.get(&entity_id) {
+            self.components[index] = component;
+            return;
+        }
+        let index = self.components.len();
+        self.entity_id_to_index.insert(entity_id, index);
+        self.index_to_entity_id.insert(index, entity_id);
+        self.components.push(component);
     }
-    pub fn get_size(&self) -> usize {
-        self.components.len()
+    pub fn remove(&mut self, entity_id: usize) -> Option<TComponent> {
+        let index = self.entity_id_to_index.remove(&entity_id)?;
+        let last = self.components.len() - 1;
+        let component = self.components.swap_remove(index);
+        let moved_entity_id = self
+            .index_to_entity_id
+            .remove(&last)
+            .expect("every index maps to an entity");
+        if index != last {
+            self.entity_id_to_index.insert(moved_entity_id, index);
+            self.index_to_entity_id.insert(index, moved_entity_id);
+        }
+        Some(component)
     }
-    pub fn add(&mut self, component: TComponent) {
-        self.components.push(component);
+    pub fn get(&self, entity_id: usize) -> Option<&TComponent> {
+        let index = *self.entity_id_to_index.get(&entity_id)?;
+        Some(&self.components[index])
     }
-    pub fn get(&self, index: usize) -> &TComponent {
-        &self.components[index]
+    pub fn get_mut(&mut self, entity_id: usize) -> Option<&mut TComponent> {
+        let index = *self.entity_id_to_index.get(&entity_id)?;
+        Some(&mut self.components[index])
+    }
+    // (entity id, component) in storage order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &TComponent)> {
+        self.components
+            .iter()
+            .enumerate()
+            .map(|(index, component)| (self.index_to_entity_id[&index], component))
     }
-    pub fn set(&mut self, index: usize, component: TComponent) {
-        self.components[index] = component;
+}
+
+impl<TComponent: 'static> IPool for Pool<TComponent> {
+    fn remove_entity_from_pool(&mut self, entity_id: usize) {
+        self.remove(entity_id);
     }
 }
 
@@ -80,36 +110,61 @@ impl<TComponent> IndexMut<usize> for Pool<TComponent> {
     }
 }
 
+// component ids index the signature bits
 const MAX_COMPONENTS: u8 = 32;
 
 type Signature = BitSet;
 
-// TODO make this a Trait
-pub struct System {
+// Entity bookkeeping every system has: the components it requires and the
+// entities that currently have all of them. Kept up to date by the Registry.
+#[derive(Default)]
+pub struct SystemBase {
     component_signature: Signature,
     entities: Vec<Entity>,
 }
 
-impl System {
+impl SystemBase {
     pub fn new() -> Self {
-        Self {
-            component_signature: BitSet::new(),
-            entities: Vec::new(),
+        Self::default()
+    }
+    pub fn require_component<TComponent: Component>(&mut self) {
+        self.component_signature.set(TComponent::get_id(), true);
+    }
+    pub fn get_component_signature(&self) -> &Signature {
+        &self.component_signature
+    }
+    pub fn get_system_entities(&self) -> &[Entity] {
+        &self.entities
+    }
+    pub fn add_entity_to_system(&mut self, entity: Entity) {
+        if !self.entities.contains(&entity) {
+            self.entities.push(entity);
         }
     }
-    // pub add_entity_to_system(entity: Entity) {}
-    // pub remove_entity_from_system(entity: Entity) {}
-    // pub get_system_entities() -> Vec<Entity> {}
-    // pub require_component() {}
+    pub fn remove_entity_from_system(&mut self, entity: Entity) {
+        self.entities.retain(|other| *other != entity);
+    }
+}
+
+// Systems are owned by the Registry and run in the order they were added,
+// see Registry::update_systems
+pub trait System: Any {
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+    fn base(&self) -> &SystemBase;
+    fn base_mut(&mut self) -> &mut SystemBase;
+    fn update(&mut self, registry: &mut Registry, events: &mut EventBus, dt: f64);
 }
 
-// TODO how to use Pool type without "caring" about Pool's generic?
 pub struct Registry {
     n_entities: usize,
-    // component_pools: Vec<Box<Pool>>,
-    entity_component_signatures: Vec<Signature>,
-    // change to Arc from Rc due to new registry singleton via lazy_static, for thread-safe atomic operations, do I need this, how do I know when this game engine is using multiple threads, how do I design for it... for later.. just get it working on 1 thread
-    systems: HashMap<TypeId, Rc<System>>,  
+    component_pools: Vec<Option<Box<dyn IPool>>>, // indexed by component id
+    entity_component_signatures: Vec<Signature>,  // indexed by entity id
+    systems: Vec<Box<dyn System>>,                // in update order
+    system_timings: Vec<(String, Duration)>,      // from the last update_systems
+    // new entities and ones whose components changed, (re)matched to systems on update
     entities_to_be_added: HashSet<Entity>,
     entities_to_be_killed: HashSet<Entity>,
 
@@ -120,18 +175,56 @@ pub struct Registry {
     // entities_per_group: HashMap<String, HashSet<Entity>>
     // group_per_entity: HashMap<usize, String>
     free_ids: VecDeque<usize>,
+
+    // singletons shared with systems, one per type, eg InputState
+    resources: HashMap<TypeId, Box<dyn Any>>,
 }
 
 impl Registry {
     pub fn new() -> Self {
         Self {
             n_entities: 0,
-            // component_pools: Vec::new(),
+            component_pools: Vec::new(),
             entity_component_signatures: Vec::new(),
-            systems: HashMap::new(),
+            systems: Vec::new(),
+            system_timings: Vec::new(),
             entities_to_be_added: HashSet::new(),
             entities_to_be_killed: HashSet::new(),
             free_ids: VecDeque::new(),
+            resources: HashMap::new(),
+        }
+    }
+
+    // Applies the frame's queued entity changes, call once per frame before the systems run
+    pub fn update(&mut self) {
+        let mut added: Vec<Entity> = self.entities_to_be_added.drain().collect();
+        added.sort();
+        for entity in added {
+            self.add_entity_to_systems(entity);
+        }
+
+        // children die with their parent, eg the turret with its tank
+        let mut pending: Vec<Entity> = self.entities_to_be_killed.iter().copied().collect();
+        while let Some(parent) = pending.pop() {
+            for child in self.get_children(parent) {
+                if self.entities_to_be_killed.insert(child) {
+                    pending.push(child);
+                }
+            }
+        }
+
+        let mut killed: Vec<Entity> = self.entities_to_be_killed.drain().collect();
+        killed.sort();
+        for entity in killed {
+            if !self.is_alive(entity) {
+                continue;
+            }
+            self.remove_entity_from_systems(entity);
+            for pool in self.component_pools.iter_mut().flatten() {
+                pool.remove_entity_from_pool(entity.get_id());
+            }
+            self.entity_component_signatures[entity.get_id()].reset();
+            self.free_ids.push_back(entity.get_id());
         }
     }
 
@@ -152,66 +245,223 @@ impl Registry {
             entity_id = self.free_ids.pop_front().unwrap();
         }
 
-        Entity::new(entity_id)
+        if entity_id >= self.entity_component_signatures.len() {
+            self.entity_component_signatures
+                .resize(entity_id + 1, Signature::default());
+        }
+
+        let entity = Entity::new(entity_id);
+        self.entities_to_be_added.insert(entity);
+        entity
         // (engdelta) entity.registry = this;  // entity must use get_instance, eg registry::KillEntity, TagEntity, GroupEntity, HasTag, HasGroup
     }
 
+    // removed on the next update, until then its components are still readable.
+    // Killing an entity that is already dead does nothing.
     pub fn kill_entity(&mut self, entity: Entity) {
-        self.entities_to_be_killed.insert(entity);
+        if self.is_alive(entity) {
+            self.entities_to_be_killed.insert(entity);
+        }
+    }
+
+    // created and not yet removed by an update, a killed entity is alive until then
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let id = entity.get_id();
+        (1..=self.n_entities).contains(&id) && !self.free_ids.contains(&id)
+    }
+
+    // entities created and not yet killed by an update
+    pub fn entity_count(&self) -> usize {
+        self.n_entities - self.free_ids.len()
+    }
+
+    // direct children only, see ParentComponent
+    pub fn get_children(&self, parent: Entity) -> Vec<Entity> {
+        self.get_pool::<ParentComponent>()
+            .map(|pool| {
+                pool.iter()
+                    .filter(|(_, component)| component.parent == parent)
+                    .map(|(entity_id, _)| Entity::new(entity_id))
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     // * Component Management
-    pub fn add_component<T: Component, Args>(&mut self, entity: Entity, args: Args) where T: NewFromArgs<Args> {
+    pub fn add_component<T, Args>(&mut self, entity: Entity, args: Args)
+    where
+        T: Component + NewFromArgs<Args>,
+    {
+        // a killed id may be handed out again, a stale handle must not touch it
+        debug_assert!(
+            self.is_alive(entity),
+            "add_component on dead entity {}",
+            entity.get_id()
+        );
+        if !self.is_alive(entity) {
+            return;
+        }
         let component = T::new(args);
+        let component_id = T::get_id();
+        assert!(
+            component_id < MAX_COMPONENTS as u32,
+            "at most {} component types are supported",
+            MAX_COMPONENTS - 1
+        );
+
+        let pool_index = component_id as usize;
+        if pool_index >= self.component_pools.len() {
+            self.component_pools.resize_with(pool_index + 1, || None);
+        }
+        self.component_pools[pool_index].get_or_insert_with(|| Box::new(Pool::<T>::new()));
+        self.get_pool_mut::<T>()
+            .expect("pools are stored under their component's id")
+            .set(entity.get_id(), component);
+
+        // update the entity's component signature for the added component
+        self.entity_component_signatures[entity.get_id()].set(component_id, true);
+        self.entities_to_be_added.insert(entity);
+    }
 
-        // (cppeng)
-        // const auto componentId = Component<TComponent>::GetId();
-        // const auto entityId = entity.GetId();
+    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let component = self.get_pool_mut::<T>()?.remove(entity.get_id())?;
+        self.entity_component_signatures[entity.get_id()].set(T::get_id(), false);
+        self.entities_to_be_added.insert(entity);
+        Some(component)
+    }
 
-        // if (componentId >= componentPools.size()) {
-        // 	componentPools.resize(componentId + 1, nullptr); // putting nothing there during resize, thus nullptr
-        // }
+    pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
+        self.entity_component_signatures
+            .get(entity.get_id())
+            .is_some_and(|signature| signature.get(T::get_id() as usize))
+    }
 
-        // if (!componentPools[componentId]) {
-        // 	std::shared_ptr<Pool<TComponent>> newComponentPool = std::make_shared<Pool<TComponent>>();
-        // 	componentPools[componentId] = newComponentPool;
-        // }
+    pub fn get_component<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.get_pool::<T>()?.get(entity.get_id())
+    }
 
-        // // ? is type cast really needed?
-        // std::shared_ptr<Pool<TComponent>> componentPool = std::static_pointer_cast<Pool<TComponent>>(componentPools[componentId]);
+    pub fn get_component_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_pool_mut::<T>()?.get_mut(entity.get_id())
+    }
 
-        // // superceded by the new tracker hashmaps and updated Pool::Set call below
-        // // if (entityId >= componentPool->GetSize()) {
-        // // 	componentPool->Resize(numEntities);
-        // // }
+    fn get_pool<T: Component>(&self) -> Option<&Pool<T>> {
+        let pool = self.component_pools.get(T::get_id() as usize)?.as_ref()?;
+        (pool.as_ref() as &dyn Any).downcast_ref()
+    }
 
-        // // forward args to constructor
-        // TComponent newComponent(std::forward<TArgs>(args)...);
+    fn get_pool_mut<T: Component>(&mut self) -> Option<&mut Pool<T>> {
+        let pool = self
+            .component_pools
+            .get_mut(T::get_id() as usize)?
+            .as_mut()?;
+        (pool.as_mut() as &mut dyn Any).downcast_mut()
+    }
 
-        // componentPool->Set(entityId, newComponent);
+    // * System Management
+    // replaces any existing system of the same type, entities join it on the next update
+    pub fn add_system<T: System>(&mut self, system: T) {
+        self.remove_system::<T>();
+        self.systems.push(Box::new(system));
+        self.entities_to_be_added.extend(self.live_entities());
+    }
 
-        // // remember ea Component is strictly data related to entity
-        // componentPool->Set(entityId, newComponent);
+    pub fn remove_system<T: System>(&mut self) {
+        self.systems
+            .retain(|system| !(system.as_ref() as &dyn Any).is::<T>());
+    }
 
-        // // update the entity's component signature for the added component
-        // entityComponentSignatures[entityId].set(componentId);
+    pub fn has_system<T: System>(&self) -> bool {
+        self.get_system::<T>().is_some()
+    }
 
-        // // Logger::Log("Component id = " + std::to_string(componentId) + " was added to entity id " +std::to_string(entityId));
+    pub fn get_system<T: System>(&self) -> Option<&T> {
+        self.systems
+            .iter()
+            .find_map(|system| (system.as_ref() as &dyn Any).downcast_ref())
     }
 
-    // pub fn remove_component<TComponent>(entity: Entity) {}
-    // pub fn has_component<TComponent>(entity: Entity) -> bool {}
-    // pub fn get_component<Tcomponent>(entity: Entity) -> TComponent {}
+    pub fn get_system_mut<T: System>(&mut self) -> Option<&mut T> {
+        self.systems
+            .iter_mut()
+            .find_map(|system| (system.as_mut() as &mut dyn Any).downcast_mut())
+    }
 
-    // * System Management
-    // TODO pub fn add_system<TSystem, TArgs>(args: TArgs) {}
-    // pub fn remove_system<TSystem>() {}
-    // pub fn has_system<TSystem>() -> bool {}
-    // pub fn get_system<TSystem>() -> TSystem {}
+    // Systems are taken out while they run so they can borrow the registry
+    pub fn update_systems(&mut self, events: &mut EventBus, dt: f64) {
+        let mut systems = std::mem::take(&mut self.systems);
+        self.system_timings.clear();
+        for system in systems.iter_mut() {
+            let start = Instant::now();
+            system.update(self, events, dt);
+            self.system_timings
+                .push((system.name().to_string(), start.elapsed()));
+        }
+        // keep systems added while running
+        let added = std::mem::replace(&mut self.systems, systems);
+        self.systems.extend(added);
+    }
+
+    // (system name, update time) from the last update_systems, in update order
+    pub fn system_timings(&self) -> &[(String, Duration)] {
+        &self.system_timings
+    }
 
     // * System-Entity Management
-    // pub fn add_entity_to_systems(entity: Entity) {}
-    // pub fn remove_entity_from_systems(entity: Entity) {}
+    // an entity belongs to every system whose required components it has
+    fn add_entity_to_systems(&mut self, entity: Entity) {
+        let signature = self.entity_component_signatures[entity.get_id()];
+        for system in self.systems.iter_mut() {
+            let base = system.base_mut();
+            let required = *base.get_component_signature();
+            if signature.intersection(&required) == required {
+                base.add_entity_to_system(entity);
+            } else {
+                base.remove_entity_from_system(entity);
+            }
+        }
+    }
+
+    fn remove_entity_from_systems(&mut self, entity: Entity) {
+        for system in self.systems.iter_mut() {
+            system.base_mut().remove_entity_from_system(entity);
+        }
+    }
+
+    fn live_entities(&self) -> Vec<Entity> {
+        (1..=self.n_entities)
+            .filter(|id| !self.free_ids.contains(id))
+            .map(Entity::new)
+            .collect()
+    }
+
+    // * Resource Management
+    // replaces any existing resource of the same type
+    pub fn add_resource<T: Any>(&mut self, resource: T) {
+        self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+    }
+
+    pub fn remove_resource<T: Any>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast().ok())
+            .map(|resource| *resource)
+    }
+
+    pub fn has_resource<T: Any>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get_resource<T: Any>(&self) -> Option<&T> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_ref())
+    }
+
+    pub fn get_resource_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_mut())
+    }
 
     // * Tag Management
     // pub fn tag_entity(entity: Entity, tag: &str) {}
@@ -225,3 +475,232 @@ impl Registry {
     // pub fn get_entities_by_group(group: &str) -> Vec<Entity> {}
     // pub fn remove_entity_group(entity: Entity) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::{BoxColliderComponent, TransformComponent};
+    use nalgebra::Vector2;
+
+    struct TransformOnlySystem {
+        base: SystemBase,
+    }
+
+    impl TransformOnlySystem {
+        fn new() -> Self {
+            let mut base = SystemBase::new();
+            base.require_component::<TransformComponent>();
+            Self { base }
+        }
+    }
+
+    impl System for TransformOnlySystem {
+        fn base(&self) -> &SystemBase {
+            &self.base
+        }
+
+        fn base_mut(&mut self) -> &mut SystemBase {
+            &mut self.base
+        }
+
+        fn update(&mut self, _registry: &mut Registry, _events: &mut EventBus, _dt: f64) {}
+    }
+
+    fn system_entities(registry: &Registry) -> Vec<Entity> {
+        registry
+            .get_system::<TransformOnlySystem>()
+            .unwrap()
+            .base()
+            .get_system_entities()
+            .to_vec()
+    }
+
+    #[test]
+    fn add_get_and_remove_component() {
+        let mut registry = Registry::new();
+        let entity = registry.create_entity();
+        registry.add_component::<TransformComponent, _>(
+            entity,
+            (Some(Vector2::new(1.0, 2.0)), None, None),
+        );
+
+        assert!(registry.has_component::<TransformComponent>(entity));
+        assert!(!registry.has_component::<BoxColliderComponent>(entity));
+        let transform = registry
+            .get_component::<TransformComponent>(entity)
+            .unwrap();
+        assert_eq!(transform.position(), Vector2::new(1.0, 2.0));
+
+        registry
+            .get_component_mut::<TransformComponent>(entity)
+            .unwrap()
+            .set_position(Vector2::new(3.0, 4.0));
+        let removed = registry.remove_component::<TransformComponent>(entity);
+        assert_eq!(removed.unwrap().position(), Vector2::new(3.0, 4.0));
+        assert!(!registry.has_component::<TransformComponent>(entity));
+        assert!(registry
+            .get_component::<TransformComponent>(entity)
+            .is_none());
+        assert!(registry
+            .remove_component::<TransformComponent>(entity)
+            .is_none());
+    }
+
+    #[test]
+    fn removing_a_component_keeps_the_others_in_the_pool() {
+        let mut registry = Registry::new();
+        let entities: Vec<Entity> = (0..3).map(|_| registry.create_entity()).collect();
+        for (index, &entity) in entities.iter().enumerate() {
+            let position = Vector2::new(index as f32, 0.0);
+            registry.add_component::<TransformComponent, _>(entity, (Some(position), None, None));
+        }
+
+        registry.remove_component::<TransformComponent>(entities[0]);
+        for (index, &entity) in entities.iter().enumerate().skip(1) {
+            let transform = registry
+                .get_component::<TransformComponent>(entity)
+                .unwrap();
+            assert_eq!(transform.position(), Vector2::new(index as f32, 0.0));
+        }
+    }
+
+    #[test]
+    fn kill_removes_components_and_reuses_the_id() {
+        let mut registry = Registry::new();
+        let entity = registry.create_entity();
+        registry.add_component::<TransformComponent, _>(entity, (None, None, None));
+        registry.update();
+        assert_eq!(registry.entity_count(), 1);
+
+        registry.kill_entity(entity);
+        assert!(registry.is_alive(entity));
+        assert!(registry
+            .get_component::<TransformComponent>(entity)
+            .is_some());
+        registry.update();
+        assert!(!registry.is_alive(entity));
+        assert_eq!(registry.entity_count(), 0);
+        assert!(!registry.has_component::<TransformComponent>(entity));
+        assert!(registry
+            .get_component::<TransformComponent>(entity)
+            .is_none());
+
+        let reused = registry.create_entity();
+        assert_eq!(reused.get_id(), entity.get_id());
+        assert!(!registry.has_component::<TransformComponent>(reused));
+    }
+
+    #[test]
+    fn killing_a_dead_entity_does_nothing() {
+        let mut registry = Registry::new();
+        let entity = registry.create_entity();
+        registry.kill_entity(entity);
+        registry.update();
+        registry.kill_entity(entity);
+        registry.update();
+        registry.kill_entity(Entity::new(42));
+        registry.update();
+
+        assert_eq!(registry.entity_count(), 0);
+        let first = registry.create_entity();
+        let second = registry.create_entity();
+        assert_ne!(first, second);
+        assert_eq!(registry.entity_count(), 2);
+    }
+
+    // panics in debug builds, release builds ignore the call
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "dead entity"))]
+    fn adding_a_component_to_a_dead_entity_is_rejected() {
+        let mut registry = Registry::new();
+        let entity = registry.create_entity();
+        registry.kill_entity(entity);
+        registry.update();
+
+        registry.add_component::<TransformComponent, _>(entity, (None, None, None));
+        registry.add_component::<TransformComponent, _>(Entity::new(42), (None, None, None));
+        registry.update();
+        assert!(!registry.has_component::<TransformComponent>(entity));
+        let reused = registry.create_entity();
+        assert!(!registry.has_component::<TransformComponent>(reused));
+        assert_eq!(registry.entity_count(), 1);
+    }
+
+    #[test]
+    fn children_die_with_their_parent() {
+        let mut registry = Registry::new();
+        let parent = registry.create_entity();
+        let child = registry.create_entity();
+        let grandchild = registry.create_entity();
+        let other = registry.create_entity();
+        registry.add_component::<ParentComponent, _>(child, ParentComponent { parent });
+        registry.add_component::<ParentComponent, _>(grandchild, ParentComponent { parent: child });
+        registry.update();
+        assert_eq!(registry.get_children(parent), vec![child]);
+
+        registry.kill_entity(parent);
+        registry.update();
+        assert!(!registry.is_alive(parent));
+        assert!(!registry.is_alive(child));
+        assert!(!registry.is_alive(grandchild));
+        assert!(registry.is_alive(other));
+        assert_eq!(registry.entity_count(), 1);
+    }
+
+    #[test]
+    fn entities_join_systems_matching_their_components() {
+        let mut registry = Registry::new();
+        registry.add_system(TransformOnlySystem::new());
+        let with = registry.create_entity();
+        let without = registry.create_entity();
+        registry.add_component::<TransformComponent, _>(with, (None, None, None));
+        registry.add_component::<BoxColliderComponent, _>(without, (1.0, 1.0, None));
+        assert!(system_entities(&registry).is_empty());
+
+        registry.update();
+        assert_eq!(system_entities(&registry), vec![with]);
+
+        registry.remove_component::<TransformComponent>(with);
+        registry.update();
+        assert!(system_entities(&registry).is_empty());
+
+        registry.add_component::<TransformComponent, _>(without, (None, None, None));
+        registry.update();
+        assert_eq!(system_entities(&registry), vec![without]);
+
+        registry.kill_entity(without);
+        registry.update();
+        assert!(system_entities(&registry).is_empty());
+    }
+
+    #[test]
+    fn system_added_later_picks_up_existing_entities() {
+        let mut registry = Registry::new();
+        let entity = registry.create_entity();
+        registry.add_component::<TransformComponent, _>(entity, (None, None, None));
+        registry.update();
+
+        registry.add_system(TransformOnlySystem::new());
+        assert!(registry.has_system::<TransformOnlySystem>());
+        registry.update();
+        assert_eq!(system_entities(&registry), vec![entity]);
+
+        registry.remove_system::<TransformOnlySystem>();
+        assert!(!registry.has_system::<TransformOnlySystem>());
+    }
+
+    #[test]
+    fn update_systems_records_timings_in_order() {
+        let mut registry = Registry::new();
+        let mut events = EventBus::new();
+        registry.add_system(TransformOnlySystem::new());
+        registry.update_systems(&mut events, 0.016);
+
+        let names: Vec<&str> = registry
+            .system_timings()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["TransformOnlySystem"]);
+    }
+}
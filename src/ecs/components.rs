@@ -1,6 +1,11 @@
-use nalgebra::Vector2;
+use nalgebra::{Matrix3, Point2, Rotation2, Vector2};
+use sdl2::{pixels::Color, rect::Rect};
+use serde::Deserialize;
 use std::any::Any;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::ecs::Entity;
 static COMPONENT_TYPE_ID: AtomicU32 = AtomicU32::new(1);
 
 pub trait Component: Any {
@@ -29,6 +34,16 @@ pub trait NewFromArgs<Args> {
     fn new(args: Args) -> Self;
 }
 
+// a built component can be added as is, eg HealthComponent::new(..).with_regen(..)
+impl<T: Component> NewFromArgs<T> for T {
+    fn new(args: T) -> Self {
+        args
+    }
+}
+
+// Rotation is in degrees, clockwise on screen since y points down, the same as
+// SDL's render_copy_ex
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TransformComponent {
     position: Vector2<f32>,
     scale: Vector2<f32>,
@@ -46,7 +61,7 @@ impl_component!(TransformComponent);
 //     ) -> Self {
 //         Self {
 //             position: position.unwrap_or(Vector2::new(0.0, 0.0)),
-//             scale: scale.unwrap_or(Vector2::new(0.0, 0.0)),
+//             scale: scale.unwrap_or(Vector2::new(1.0, 1.0)),
 //             rotation: rotation.unwrap_or(0.0),
 //         }
 //     }
@@ -56,25 +71,545 @@ impl NewFromArgs<(Option<Vector2<f32>>, Option<Vector2<f32>>, Option<f32>)> for
     fn new(args: (Option<Vector2<f32>>, Option<Vector2<f32>>, Option<f32>)) -> Self {
         TransformComponent {
             position: args.0.unwrap_or(Vector2::new(0.0, 0.0)),
-            scale: args.1.unwrap_or(Vector2::new(0.0, 0.0)),
-            rotation: args.2.unwrap_or(0.0),
+            scale: args.1.unwrap_or(Vector2::new(1.0, 1.0)),
+            rotation: args.2.unwrap_or(0.0).rem_euclid(360.0),
         }
     }
 }
 
+impl Default for TransformComponent {
+    fn default() -> Self {
+        TransformComponent::new((None, None, None))
+    }
+}
+
+impl TransformComponent {
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vector2<f32>) {
+        self.position = position;
+    }
+
+    pub fn scale(&self) -> Vector2<f32> {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Vector2<f32>) {
+        self.scale = scale;
+    }
+
+    // degrees in 0..360
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, degrees: f32) {
+        self.rotation = degrees.rem_euclid(360.0);
+    }
+
+    pub fn translate(&mut self, delta: Vector2<f32>) {
+        self.position += delta;
+    }
+
+    pub fn rotate(&mut self, degrees: f32) {
+        self.set_rotation(self.rotation + degrees);
+    }
+
+    // Turns so forward() points at target, unchanged if target is the position
+    pub fn look_at(&mut self, target: Vector2<f32>) {
+        let to_target = target - self.position;
+        if to_target != Vector2::zeros() {
+            self.set_rotation(to_target.y.atan2(to_target.x).to_degrees());
+        }
+    }
+
+    // unit vector along the local +x axis
+    pub fn forward(&self) -> Vector2<f32> {
+        let radians = self.rotation.to_radians();
+        Vector2::new(radians.cos(), radians.sin())
+    }
+
+    // Local to world as a homogeneous 2D matrix: scale, then rotate, then translate
+    pub fn to_matrix(&self) -> Matrix3<f32> {
+        Matrix3::new_translation(&self.position)
+            * Rotation2::new(self.rotation.to_radians()).to_homogeneous()
+            * Matrix3::new_nonuniform_scaling(&self.scale)
+    }
+
+    // Inverse of to_matrix, a shear from non-uniform scale under rotation is lost
+    pub fn from_matrix(matrix: &Matrix3<f32>) -> Self {
+        let x_axis = Vector2::new(matrix[(0, 0)], matrix[(1, 0)]);
+        let y_axis = Vector2::new(matrix[(0, 1)], matrix[(1, 1)]);
+        let flip = if x_axis.perp(&y_axis) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        TransformComponent {
+            position: Vector2::new(matrix[(0, 2)], matrix[(1, 2)]),
+            scale: Vector2::new(x_axis.norm(), y_axis.norm() * flip),
+            rotation: x_axis.y.atan2(x_axis.x).to_degrees().rem_euclid(360.0),
+        }
+    }
+
+    pub fn transform_point(&self, local: Vector2<f32>) -> Vector2<f32> {
+        self.to_matrix()
+            .transform_point(&Point2::from(local))
+            .coords
+    }
+}
+
+// Attaches an entity to another, eg a turret on a tank. The child's TransformComponent
+// is then relative to the parent and it is killed along with the parent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParentComponent {
+    pub parent: Entity,
+}
+
+impl_component!(ParentComponent);
+
+// World space transform of a child, kept up to date by TransformSystem.
+// Entities without a parent are already in world space.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WorldTransformComponent {
+    pub transform: TransformComponent,
+}
+
+impl_component!(WorldTransformComponent);
+
+// Drawn by RenderSystem with its top left corner at the transform's position,
+// lower z_index first. Without a texture, or while it isn't loaded, a rect of color
+// is drawn instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteComponent {
+    pub width: f32,
+    pub height: f32,
+    pub color: Color,
+    pub z_index: i32,
+    pub texture_id: Option<String>, // see AssetStore
+    pub src_rect: Option<Rect>,     // part of the texture to draw, None for all of it
+}
+
+impl_component!(SpriteComponent);
+
+impl SpriteComponent {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            color: Color::WHITE,
+            z_index: 0,
+            texture_id: None,
+            src_rect: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_z_index(mut self, z_index: i32) -> Self {
+        self.z_index = z_index;
+        self
+    }
+
+    pub fn with_texture(mut self, texture_id: &str, src_rect: Option<Rect>) -> Self {
+        self.texture_id = Some(texture_id.to_string());
+        self.src_rect = src_rect;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+// Drawn by RenderTextSystem, eg HUD and menus. The text block is anchored at
+// position by align: Left starts there, Center centers on it, Right ends there.
+// Lines wider than wrap_width break at spaces, "\n" always breaks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLabelComponent {
+    pub text: String,
+    pub font_id: String, // see TextRenderer::add_font
+    pub font_size: u16,
+    pub color: Color,
+    pub position: Vector2<f32>,
+    pub is_fixed: bool, // screen coordinates, otherwise world coordinates through the camera
+    pub align: TextAlign,
+    pub wrap_width: Option<u32>, // pixels
+}
+
+impl_component!(TextLabelComponent);
+
+impl TextLabelComponent {
+    pub fn new(text: &str, font_id: &str, font_size: u16) -> Self {
+        Self {
+            text: text.to_string(),
+            font_id: font_id.to_string(),
+            font_size,
+            color: Color::WHITE,
+            position: Vector2::new(0.0, 0.0),
+            is_fixed: true,
+            align: TextAlign::Left,
+            wrap_width: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_position(mut self, position: Vector2<f32>, is_fixed: bool) -> Self {
+        self.position = position;
+        self.is_fixed = is_fixed;
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_wrap_width(mut self, wrap_width: Option<u32>) -> Self {
+        self.wrap_width = wrap_width;
+        self
+    }
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+// A run of equally sized frames on one row of a sprite sheet, eg
+// [tank.idle] in assets/animations.toml, see AssetStore::load_animations
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnimationClip {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub frame_count: u32,
+    pub frame_rate: f32, // frames per second
+    #[serde(default)]
+    pub row: u32,
+    #[serde(default)]
+    pub first_frame: u32, // column of the first frame
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+}
+
+impl AnimationClip {
+    pub fn src_rect(&self, frame: u32) -> Rect {
+        Rect::new(
+            ((self.first_frame + frame) * self.frame_width) as i32,
+            (self.row * self.frame_height) as i32,
+            self.frame_width,
+            self.frame_height,
+        )
+    }
+}
+
+// by clip name, eg "idle", "walk"
+pub type AnimationClips = BTreeMap<String, AnimationClip>;
+
+// Plays one of its clips, advanced by AnimationSystem which also updates the
+// sprite's src_rect
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationComponent {
+    clips: AnimationClips,
+    clip: String,
+    frame: u32,
+    elapsed: f64, // seconds shown of the current frame
+    is_finished: bool,
+}
+
+impl_component!(AnimationComponent);
+
+impl AnimationComponent {
+    // None if clips has no clip named initial_clip
+    pub fn new(clips: AnimationClips, initial_clip: &str) -> Option<Self> {
+        clips.contains_key(initial_clip).then(|| Self {
+            clips,
+            clip: initial_clip.to_string(),
+            frame: 0,
+            elapsed: 0.0,
+            is_finished: false,
+        })
+    }
+
+    pub fn clips(&self) -> &AnimationClips {
+        &self.clips
+    }
+
+    pub fn clip_name(&self) -> &str {
+        &self.clip
+    }
+
+    pub fn clip(&self) -> &AnimationClip {
+        &self.clips[&self.clip]
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // a non looping clip that reached its end, it stays on its last frame
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    // Switches clip from its first frame, playing the current clip again does not
+    // restart it. Returns false for an unknown clip.
+    pub fn play(&mut self, clip: &str) -> bool {
+        if !self.clips.contains_key(clip) {
+            return false;
+        }
+        if self.clip != clip {
+            self.clip = clip.to_string();
+            self.restart();
+        }
+        true
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.is_finished = false;
+    }
+
+    pub fn advance(&mut self, dt: f64) {
+        let clip = &self.clips[&self.clip];
+        if self.is_finished || clip.frame_rate <= 0.0 || clip.frame_count == 0 {
+            return;
+        }
+        let frame_time = 1.0 / clip.frame_rate as f64;
+        self.elapsed += dt;
+        let steps = (self.elapsed / frame_time).floor();
+        self.elapsed -= steps * frame_time;
+        let next = self.frame as u64 + steps as u64;
+        let frame_count = clip.frame_count as u64;
+        if clip.looping {
+            self.frame = (next % frame_count) as u32;
+        } else if next >= frame_count {
+            self.frame = clip.frame_count - 1;
+            self.elapsed = 0.0;
+            self.is_finished = true;
+        } else {
+            self.frame = next as u32;
+        }
+    }
+
+    pub fn src_rect(&self) -> Rect {
+        self.clip().src_rect(self.frame)
+    }
+}
+
+// Axis aligned box in the entity's local space, scaled by its transform.
+// Offset is from the transform's position to the box's top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxColliderComponent {
+    pub width: f32,
+    pub height: f32,
+    pub offset: Vector2<f32>,
+}
+
+impl_component!(BoxColliderComponent);
+
+impl NewFromArgs<(f32, f32, Option<Vector2<f32>>)> for BoxColliderComponent {
+    fn new(args: (f32, f32, Option<Vector2<f32>>)) -> Self {
+        BoxColliderComponent {
+            width: args.0,
+            height: args.1,
+            offset: args.2.unwrap_or(Vector2::new(0.0, 0.0)),
+        }
+    }
+}
 
 pub struct HealthComponent {
     hp: u16,
     max_hp: u16,
+    regen_per_sec: f32,
+    regen_progress: f32,         // fraction of a point regenerated so far
+    invulnerability_window: f64, // seconds of invulnerability after taking damage
+    invulnerable_for: f64,       // seconds left
 }
 
 impl_component!(HealthComponent);
 
 impl HealthComponent {
     pub fn new(hp: Option<u16>, max_hp: Option<u16>) -> Self {
+        let max_hp = max_hp.unwrap_or(100);
         Self {
-            hp: hp.unwrap_or(100),
-            max_hp: max_hp.unwrap_or(100),
+            hp: hp.unwrap_or(max_hp).min(max_hp),
+            max_hp,
+            regen_per_sec: 0.0,
+            regen_progress: 0.0,
+            invulnerability_window: 0.0,
+            invulnerable_for: 0.0,
+        }
+    }
+
+    pub fn with_regen(mut self, hp_per_sec: f32) -> Self {
+        self.regen_per_sec = hp_per_sec.max(0.0);
+        self
+    }
+
+    pub fn with_invulnerability_window(mut self, secs: f64) -> Self {
+        self.invulnerability_window = secs.max(0.0);
+        self
+    }
+
+    pub fn hp(&self) -> u16 {
+        self.hp
+    }
+
+    pub fn max_hp(&self) -> u16 {
+        self.max_hp
+    }
+
+    // lowering max hp lowers hp with it
+    pub fn set_max_hp(&mut self, max_hp: u16) {
+        self.max_hp = max_hp;
+        self.hp = self.hp.min(max_hp);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp == 0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_for > 0.0
+    }
+
+    pub fn make_invulnerable(&mut self, secs: f64) {
+        self.invulnerable_for = self.invulnerable_for.max(secs);
+    }
+
+    // Returns the hp actually lost, 0 while invulnerable or dead.
+    // Taking damage starts the invulnerability window.
+    pub fn damage(&mut self, amount: u16) -> u16 {
+        if self.is_dead() || self.is_invulnerable() || amount == 0 {
+            return 0;
+        }
+        let dealt = amount.min(self.hp);
+        self.hp -= dealt;
+        self.regen_progress = 0.0;
+        self.invulnerable_for = self.invulnerability_window;
+        dealt
+    }
+
+    // Returns the hp actually gained, the dead can't be healed
+    pub fn heal(&mut self, amount: u16) -> u16 {
+        if self.is_dead() {
+            return 0;
+        }
+        let healed = amount.min(self.max_hp - self.hp);
+        self.hp += healed;
+        healed
+    }
+
+    // Counts down invulnerability and applies regen, see HealthSystem
+    pub fn tick(&mut self, dt: f64) {
+        self.invulnerable_for = (self.invulnerable_for - dt).max(0.0);
+        if self.is_dead() || self.regen_per_sec == 0.0 || self.hp == self.max_hp {
+            self.regen_progress = 0.0;
+            return;
+        }
+        self.regen_progress += self.regen_per_sec * dt as f32;
+        let whole_points = self.regen_progress.floor();
+        self.regen_progress -= whole_points;
+        self.heal(whole_points.min(u16::MAX as f32) as u16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_is_kept_in_0_to_360() {
+        let transform = TransformComponent::new((None, None, Some(-90.0)));
+        assert_eq!(transform.rotation(), 270.0);
+        let transform = TransformComponent::new((None, None, Some(450.0)));
+        assert_eq!(transform.rotation(), 90.0);
+
+        let mut transform = TransformComponent::default();
+        transform.rotate(-30.0);
+        assert_eq!(transform.rotation(), 330.0);
+        transform.set_rotation(720.0);
+        assert_eq!(transform.rotation(), 0.0);
+    }
+
+    #[test]
+    fn from_matrix_undoes_to_matrix() {
+        let transform = TransformComponent::new((
+            Some(Vector2::new(3.0, -4.0)),
+            Some(Vector2::new(2.0, 0.5)),
+            Some(-45.0),
+        ));
+        let back = TransformComponent::from_matrix(&transform.to_matrix());
+        assert!((back.position() - transform.position()).norm() < 1e-4);
+        assert!((back.scale() - transform.scale()).norm() < 1e-4);
+        assert!((back.rotation() - transform.rotation()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn overkill_is_clamped_to_the_hp_left() {
+        let mut health = HealthComponent::new(Some(30), Some(100));
+        assert_eq!(health.damage(50), 30);
+        assert_eq!(health.hp(), 0);
+        assert!(health.is_dead());
+        assert_eq!(health.damage(10), 0);
+    }
+
+    #[test]
+    fn invulnerability_blocks_hits_until_it_runs_out() {
+        let mut health = HealthComponent::new(None, None).with_invulnerability_window(0.5);
+        assert_eq!(health.damage(10), 10);
+        assert!(health.is_invulnerable());
+        assert_eq!(health.damage(10), 0);
+
+        health.tick(0.25);
+        assert_eq!(health.damage(10), 0);
+        health.tick(0.25);
+        assert!(!health.is_invulnerable());
+        assert_eq!(health.damage(10), 10);
+        assert_eq!(health.hp(), 80);
+    }
+
+    #[test]
+    fn fractional_regen_builds_up_across_ticks() {
+        let mut health = HealthComponent::new(Some(50), Some(100)).with_regen(0.25);
+        for _ in 0..3 {
+            health.tick(1.0);
+            assert_eq!(health.hp(), 50);
         }
+        health.tick(1.0);
+        assert_eq!(health.hp(), 51);
+
+        // regen stops at max hp
+        let mut health = HealthComponent::new(Some(99), Some(100)).with_regen(10.0);
+        health.tick(1.0);
+        assert_eq!(health.hp(), 100);
+    }
+
+    #[test]
+    fn the_dead_are_not_healed() {
+        let mut health = HealthComponent::new(Some(10), Some(100)).with_regen(5.0);
+        health.damage(10);
+        assert_eq!(health.heal(20), 0);
+        health.tick(1.0);
+        assert!(health.is_dead());
+
+        let mut health = HealthComponent::new(Some(90), Some(100));
+        assert_eq!(health.heal(20), 10);
+        assert_eq!(health.hp(), 100);
     }
 }
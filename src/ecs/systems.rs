@@ -1,4 +1,23 @@
-// use crate::ecs::System;
+use nalgebra::{Matrix3, Vector2};
+use sdl2::{
+    rect::Rect,
+    render::{Canvas, RenderTarget},
+    video::Window,
+};
+use std::collections::HashMap;
+
+use crate::asset_store::AssetStore;
+use crate::camera::Camera;
+use crate::kdbg;
+use crate::text::TextRenderer;
+
+use crate::ecs::components::{
+    AnimationComponent, BoxColliderComponent, HealthComponent, ParentComponent, SpriteComponent,
+    TextLabelComponent, TransformComponent, WorldTransformComponent,
+};
+use crate::ecs::{Entity, Registry, System, SystemBase};
+use crate::event_bus::EventBus;
+
 struct MovementSystem {}
 
 // impl System for MovementSystem {
@@ -6,3 +25,639 @@ struct MovementSystem {}
 //     let entities = get_system_entities();
 // }
 // }
+
+// Deeper hierarchies are treated as a parent cycle and cut off
+const MAX_HIERARCHY_DEPTH: usize = 64;
+
+// The entity's transform in world space, for children as of the last TransformSystem update
+pub fn world_transform(registry: &Registry, entity: Entity) -> Option<TransformComponent> {
+    if registry.has_component::<ParentComponent>(entity) {
+        if let Some(world) = registry.get_component::<WorldTransformComponent>(entity) {
+            return Some(world.transform);
+        }
+    }
+    registry
+        .get_component::<TransformComponent>(entity)
+        .copied()
+}
+
+// Parent's world matrix times the local one, memoized in cache for shared ancestors.
+// A parent without a TransformComponent ends the chain, the child is then in world space.
+fn world_matrix(
+    registry: &Registry,
+    entity: Entity,
+    cache: &mut HashMap<Entity, Matrix3<f32>>,
+    depth: usize,
+) -> Option<Matrix3<f32>> {
+    if let Some(matrix) = cache.get(&entity) {
+        return Some(*matrix);
+    }
+    let local = registry
+        .get_component::<TransformComponent>(entity)?
+        .to_matrix();
+    let parent_matrix = registry
+        .get_component::<ParentComponent>(entity)
+        .filter(|_| depth < MAX_HIERARCHY_DEPTH)
+        .and_then(|component| world_matrix(registry, component.parent, cache, depth + 1));
+    let matrix = parent_matrix.map_or(local, |parent_matrix| parent_matrix * local);
+    cache.insert(entity, matrix);
+    Some(matrix)
+}
+
+// Computes WorldTransformComponent for every child from its local transform and
+// its ancestors'. Added before the systems that read world positions.
+pub struct TransformSystem {
+    base: SystemBase,
+}
+
+impl TransformSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<TransformComponent>();
+        base.require_component::<ParentComponent>();
+        Self { base }
+    }
+}
+
+impl Default for TransformSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for TransformSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, registry: &mut Registry, _events: &mut EventBus, _dt: f64) {
+        let mut cache = HashMap::new();
+        for &entity in self.base.get_system_entities() {
+            let Some(matrix) = world_matrix(registry, entity, &mut cache, 0) else {
+                continue;
+            };
+            let transform = TransformComponent::from_matrix(&matrix);
+            match registry.get_component_mut::<WorldTransformComponent>(entity) {
+                Some(world) => world.transform = transform,
+                None => registry.add_component::<WorldTransformComponent, _>(
+                    entity,
+                    WorldTransformComponent { transform },
+                ),
+            }
+        }
+    }
+}
+
+// Emitted once per frame for every overlapping pair, a has the lower entity id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+// World space box, boxes that only touch edges don't overlap
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector2<f32>, max: Vector2<f32>) -> Self {
+        Self { min, max }
+    }
+
+    // a negative scale flips the box, min and max are sorted back out
+    pub fn from_collider(transform: &TransformComponent, collider: &BoxColliderComponent) -> Self {
+        let scale = transform.scale();
+        let corner = transform.position() + collider.offset.component_mul(&scale);
+        let far_corner = corner + Vector2::new(collider.width * scale.x, collider.height * scale.y);
+        Self {
+            min: corner.inf(&far_corner),
+            max: corner.sup(&far_corner),
+        }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && other.min.x < self.max.x
+            && self.min.y < other.max.y
+            && other.min.y < self.max.y
+    }
+}
+
+// Sweep and prune broad phase: boxes sorted by min x are swept left to right and
+// each is only tested against the still open boxes whose x range it overlaps.
+// Returns pairs ordered by entity id.
+pub fn find_overlapping_pairs(boxes: &[(Entity, Aabb)]) -> Vec<(Entity, Entity)> {
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|&i, &j| boxes[i].1.min.x.total_cmp(&boxes[j].1.min.x));
+
+    let mut pairs = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for i in order {
+        let (entity, aabb) = &boxes[i];
+        open.retain(|&j| boxes[j].1.max.x > aabb.min.x);
+        for &j in &open {
+            let (other, other_aabb) = &boxes[j];
+            if aabb.intersects(other_aabb) {
+                pairs.push((*entity.min(other), *entity.max(other)));
+            }
+        }
+        open.push(i);
+    }
+    pairs.sort();
+    pairs
+}
+
+pub struct CollisionSystem {
+    base: SystemBase,
+}
+
+impl CollisionSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<TransformComponent>();
+        base.require_component::<BoxColliderComponent>();
+        Self { base }
+    }
+}
+
+impl Default for CollisionSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for CollisionSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, registry: &mut Registry, events: &mut EventBus, _dt: f64) {
+        let boxes: Vec<(Entity, Aabb)> = self
+            .base
+            .get_system_entities()
+            .iter()
+            .filter_map(|&entity| {
+                let transform = world_transform(registry, entity)?;
+                let collider = registry.get_component::<BoxColliderComponent>(entity)?;
+                Some((entity, Aabb::from_collider(&transform, collider)))
+            })
+            .collect();
+
+        for (a, b) in find_overlapping_pairs(&boxes) {
+            events.emit(registry, CollisionEvent { a, b });
+        }
+    }
+}
+
+// Emitted by HealthSystem the frame an entity's hp reaches 0, before it is killed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeathEvent {
+    pub entity: Entity,
+}
+
+// Ticks invulnerability and regen, and kills entities that ran out of hp
+pub struct HealthSystem {
+    base: SystemBase,
+}
+
+impl HealthSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<HealthComponent>();
+        Self { base }
+    }
+}
+
+impl Default for HealthSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for HealthSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, registry: &mut Registry, events: &mut EventBus, dt: f64) {
+        for &entity in self.base.get_system_entities() {
+            let Some(health) = registry.get_component_mut::<HealthComponent>(entity) else {
+                continue;
+            };
+            health.tick(dt);
+            if health.is_dead() {
+                // the kill lands on the next Registry::update, so handlers can still read it
+                events.emit(registry, DeathEvent { entity });
+                registry.kill_entity(entity);
+            }
+        }
+    }
+}
+
+// Where a sprite lands on screen, None if it is entirely off screen
+pub fn sprite_screen_rect(
+    camera: &Camera,
+    transform: &TransformComponent,
+    sprite: &SpriteComponent,
+) -> Option<Rect> {
+    let size = Vector2::new(sprite.width, sprite.height).component_mul(&transform.scale());
+    let corner = camera.world_to_screen(transform.position());
+    let far_corner = camera.world_to_screen(transform.position() + size);
+    let (min, max) = (corner.inf(&far_corner), corner.sup(&far_corner));
+    let viewport = camera.viewport();
+    if max.x <= 0.0 || max.y <= 0.0 || min.x >= viewport.x || min.y >= viewport.y {
+        return None;
+    }
+    let (x, y) = (min.x.floor(), min.y.floor());
+    let width = (max.x.ceil() - x) as u32;
+    let height = (max.y.ceil() - y) as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(Rect::new(x as i32, y as i32, width, height))
+}
+
+// Draws sprites through the Camera resource. Runs from Game::render rather than
+// update, since it needs the canvas.
+pub struct RenderSystem {
+    base: SystemBase,
+}
+
+impl RenderSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<TransformComponent>();
+        base.require_component::<SpriteComponent>();
+        Self { base }
+    }
+
+    pub fn render<T: RenderTarget>(&self, registry: &Registry, canvas: &mut Canvas<T>) {
+        // without a camera resource world coordinates are screen coordinates
+        let camera = match registry.get_resource::<Camera>() {
+            Some(camera) => camera.clone(),
+            None => {
+                let (width, height) = canvas.output_size().unwrap_or((0, 0));
+                Camera::new(width, height)
+            }
+        };
+
+        let assets = registry.get_resource::<AssetStore>();
+
+        let mut sprites: Vec<(Entity, TransformComponent, &SpriteComponent)> = self
+            .base
+            .get_system_entities()
+            .iter()
+            .filter_map(|&entity| {
+                let transform = world_transform(registry, entity)?;
+                let sprite = registry.get_component::<SpriteComponent>(entity)?;
+                Some((entity, transform, sprite))
+            })
+            .collect();
+        sprites.sort_by_key(|(entity, _, sprite)| (sprite.z_index, *entity));
+
+        for (entity, transform, sprite) in sprites {
+            let Some(rect) = sprite_screen_rect(&camera, &transform, sprite) else {
+                continue;
+            };
+            let texture = sprite
+                .texture_id
+                .as_deref()
+                .and_then(|id| assets?.get_texture(id));
+            if let Some(texture) = texture {
+                let scale = transform.scale();
+                let result = canvas.copy_ex(
+                    texture,
+                    sprite.src_rect,
+                    rect,
+                    transform.rotation() as f64,
+                    None,
+                    scale.x < 0.0,
+                    scale.y < 0.0,
+                );
+                match result {
+                    Ok(()) => continue,
+                    // eg the software canvas of render_to_surface, fall back to a rect
+                    Err(e) => kdbg!("Failed to draw entity {}: {}", entity.get_id(), e),
+                }
+            }
+            canvas.set_draw_color(sprite.color);
+            if let Err(e) = canvas.fill_rect(rect) {
+                kdbg!("Failed to draw entity {}: {}", entity.get_id(), e);
+            }
+        }
+    }
+}
+
+impl Default for RenderSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for RenderSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, _registry: &mut Registry, _events: &mut EventBus, _dt: f64) {}
+}
+
+// Advances animations by the frame's dt and points each sprite at its current frame
+pub struct AnimationSystem {
+    base: SystemBase,
+}
+
+impl AnimationSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<SpriteComponent>();
+        base.require_component::<AnimationComponent>();
+        Self { base }
+    }
+}
+
+impl Default for AnimationSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for AnimationSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, registry: &mut Registry, _events: &mut EventBus, dt: f64) {
+        for &entity in self.base.get_system_entities() {
+            let Some(animation) = registry.get_component_mut::<AnimationComponent>(entity) else {
+                continue;
+            };
+            animation.advance(dt);
+            let src_rect = animation.src_rect();
+            if let Some(sprite) = registry.get_component_mut::<SpriteComponent>(entity) {
+                sprite.src_rect = Some(src_rect);
+            }
+        }
+    }
+}
+
+// Draws text labels through the Camera resource, unless fixed to the screen.
+// Runs from Game::render after the sprites, only with a window.
+pub struct RenderTextSystem {
+    base: SystemBase,
+}
+
+impl RenderTextSystem {
+    pub fn new() -> Self {
+        let mut base = SystemBase::new();
+        base.require_component::<TextLabelComponent>();
+        Self { base }
+    }
+
+    pub fn render(
+        &self,
+        registry: &Registry,
+        canvas: &mut Canvas<Window>,
+        text_renderer: &mut TextRenderer,
+    ) {
+        let camera = registry.get_resource::<Camera>();
+        for &entity in self.base.get_system_entities() {
+            let Some(label) = registry.get_component::<TextLabelComponent>(entity) else {
+                continue;
+            };
+            let screen_position = match camera {
+                Some(camera) if !label.is_fixed => camera.world_to_screen(label.position),
+                _ => label.position,
+            };
+            if let Err(e) = text_renderer.draw_label(canvas, label, screen_position) {
+                kdbg!("Failed to draw entity {}: {:#}", entity.get_id(), e);
+            }
+        }
+    }
+}
+
+impl Default for RenderTextSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl System for RenderTextSystem {
+    fn base(&self) -> &SystemBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut SystemBase {
+        &mut self.base
+    }
+
+    fn update(&mut self, _registry: &mut Registry, _events: &mut EventBus, _dt: f64) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::components::NewFromArgs;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn aabb(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Aabb {
+        Aabb::new(Vector2::new(min_x, min_y), Vector2::new(max_x, max_y))
+    }
+
+    fn entity(id: usize) -> Entity {
+        Entity::new(id)
+    }
+
+    #[test]
+    fn from_collider_applies_position_offset_and_scale() {
+        let transform = TransformComponent::new((
+            Some(Vector2::new(10.0, 20.0)),
+            Some(Vector2::new(2.0, 3.0)),
+            None,
+        ));
+        let collider = BoxColliderComponent::new((4.0, 5.0, Some(Vector2::new(1.0, -1.0))));
+
+        let aabb = Aabb::from_collider(&transform, &collider);
+        assert_eq!(aabb.min, Vector2::new(12.0, 17.0));
+        assert_eq!(aabb.max, Vector2::new(20.0, 32.0));
+    }
+
+    #[test]
+    fn from_collider_with_negative_scale_keeps_min_below_max() {
+        let transform = TransformComponent::new((
+            Some(Vector2::new(10.0, 10.0)),
+            Some(Vector2::new(-1.0, -2.0)),
+            None,
+        ));
+        let collider = BoxColliderComponent::new((4.0, 5.0, None));
+
+        let aabb = Aabb::from_collider(&transform, &collider);
+        assert_eq!(aabb.min, Vector2::new(6.0, 0.0));
+        assert_eq!(aabb.max, Vector2::new(10.0, 10.0));
+    }
+
+    #[test]
+    fn boxes_touching_edges_do_not_overlap() {
+        let boxes = [
+            (entity(1), aabb(0.0, 0.0, 10.0, 10.0)),
+            (entity(2), aabb(10.0, 0.0, 20.0, 10.0)),
+            (entity(3), aabb(0.0, 10.0, 10.0, 20.0)),
+        ];
+        assert!(find_overlapping_pairs(&boxes).is_empty());
+    }
+
+    #[test]
+    fn overlapping_pairs_are_ordered_by_entity_id() {
+        let boxes = [
+            (entity(5), aabb(0.0, 0.0, 10.0, 10.0)),
+            (entity(3), aabb(5.0, 5.0, 15.0, 15.0)),
+            (entity(1), aabb(8.0, -5.0, 9.0, 1.0)),
+            (entity(4), aabb(100.0, 0.0, 110.0, 10.0)),
+        ];
+        assert_eq!(
+            find_overlapping_pairs(&boxes),
+            vec![(entity(1), entity(5)), (entity(3), entity(5))]
+        );
+    }
+
+    #[test]
+    fn boxes_overlapping_on_x_only_are_not_paired() {
+        let boxes = [
+            (entity(1), aabb(0.0, 0.0, 10.0, 10.0)),
+            (entity(2), aabb(5.0, 20.0, 15.0, 30.0)),
+        ];
+        assert!(find_overlapping_pairs(&boxes).is_empty());
+    }
+
+    #[test]
+    fn sweep_matches_testing_every_pair() {
+        let boxes: Vec<(Entity, Aabb)> = (0..20)
+            .map(|i| {
+                let x = (i * 7 % 13) as f32 * 3.0;
+                let y = (i * 5 % 11) as f32 * 2.0;
+                (entity(i + 1), aabb(x, y, x + 5.0, y + 4.0))
+            })
+            .collect();
+        let mut expected = Vec::new();
+        for (i, (a, a_box)) in boxes.iter().enumerate() {
+            for (b, b_box) in &boxes[i + 1..] {
+                if a_box.intersects(b_box) {
+                    expected.push((*a.min(b), *a.max(b)));
+                }
+            }
+        }
+        expected.sort();
+        assert!(!expected.is_empty());
+        assert_eq!(find_overlapping_pairs(&boxes), expected);
+    }
+
+    #[test]
+    fn collision_system_emits_an_event_per_overlapping_pair() {
+        let mut registry = Registry::new();
+        let mut events = EventBus::new();
+        registry.add_system(CollisionSystem::new());
+        let collisions = Rc::new(RefCell::new(Vec::new()));
+        {
+            let collisions = collisions.clone();
+            events.subscribe::<CollisionEvent>(move |event, _, _| {
+                collisions.borrow_mut().push(*event);
+            });
+        }
+
+        let positions = [(0.0, 0.0), (5.0, 5.0), (50.0, 50.0)];
+        let entities: Vec<Entity> = positions
+            .iter()
+            .map(|&(x, y)| {
+                let entity = registry.create_entity();
+                registry.add_component::<TransformComponent, _>(
+                    entity,
+                    (Some(Vector2::new(x, y)), None, None),
+                );
+                registry.add_component::<BoxColliderComponent, _>(entity, (10.0, 10.0, None));
+                entity
+            })
+            .collect();
+        // no collider, never collides
+        let ghost = registry.create_entity();
+        registry.add_component::<TransformComponent, _>(ghost, (None, None, None));
+
+        registry.update();
+        registry.update_systems(&mut events, 0.016);
+        assert_eq!(
+            *collisions.borrow(),
+            [CollisionEvent {
+                a: entities[0],
+                b: entities[1]
+            }]
+        );
+
+        registry.kill_entity(entities[1]);
+        registry.update();
+        registry.update_systems(&mut events, 0.016);
+        assert_eq!(collisions.borrow().len(), 1);
+    }
+
+    #[test]
+    fn death_is_emitted_once_before_the_entity_is_removed() {
+        let mut registry = Registry::new();
+        let mut events = EventBus::new();
+        registry.add_system(HealthSystem::new());
+        let deaths = Rc::new(RefCell::new(Vec::new()));
+        {
+            let deaths = deaths.clone();
+            events.subscribe::<DeathEvent>(move |event, registry, _| {
+                // still readable by the handlers
+                let health = registry.get_component::<HealthComponent>(event.entity);
+                deaths
+                    .borrow_mut()
+                    .push((event.entity, health.map(|h| h.hp())));
+            });
+        }
+
+        let entity = registry.create_entity();
+        registry.add_component::<HealthComponent, _>(entity, HealthComponent::new(Some(5), None));
+        let survivor = registry.create_entity();
+        registry.add_component::<HealthComponent, _>(survivor, HealthComponent::new(None, None));
+        registry.update();
+        registry.update_systems(&mut events, 0.016);
+        assert!(deaths.borrow().is_empty());
+
+        registry
+            .get_component_mut::<HealthComponent>(entity)
+            .unwrap()
+            .damage(10);
+        registry.update_systems(&mut events, 0.016);
+        assert_eq!(*deaths.borrow(), [(entity, Some(0))]);
+        assert!(registry.is_alive(entity));
+
+        registry.update();
+        assert!(!registry.is_alive(entity));
+        assert!(registry.is_alive(survivor));
+        registry.update_systems(&mut events, 0.016);
+        assert_eq!(deaths.borrow().len(), 1);
+    }
+}
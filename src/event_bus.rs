@@ -0,0 +1,255 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::ecs::Registry;
+use crate::kwarn;
+
+// Upper bound on queued events delivered per flush, so handlers that keep queueing
+// events can't stall a frame. Anything left over is delivered next frame.
+const MAX_EVENTS_PER_FLUSH: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Handler<E> = Box<dyn FnMut(&E, &mut Registry, &mut EventBus)>;
+type Subscribers<E> = Vec<(SubscriptionId, Handler<E>)>;
+type QueuedEvent = Box<dyn FnOnce(&mut EventBus, &mut Registry)>;
+
+// Typed publish/subscribe between systems, eg a collision leading to damage.
+// Events are any 'static type. `emit` delivers right away, `queue` holds the
+// event until `flush`, which Game calls at the end of every update.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: HashMap<TypeId, Box<dyn Any>>, // Subscribers<E> per event type
+    queued: VecDeque<QueuedEvent>,
+    dispatching: HashSet<TypeId>,
+    // unsubscribed while their event type was dispatching, removed once it's done
+    unsubscribed: HashSet<SubscriptionId>,
+    clear_count: u64, // lets a dispatch notice clear() was called by a handler
+    next_id: u64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe<E: 'static>(
+        &mut self,
+        handler: impl FnMut(&E, &mut Registry, &mut EventBus) + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers_mut::<E>().push((id, Box::new(handler)));
+        id
+    }
+
+    pub fn unsubscribe<E: 'static>(&mut self, id: SubscriptionId) {
+        if self.dispatching.contains(&TypeId::of::<E>()) {
+            self.unsubscribed.insert(id);
+        }
+        self.subscribers_mut::<E>()
+            .retain(|(subscription_id, _)| *subscription_id != id);
+    }
+
+    pub fn subscriber_count<E: 'static>(&self) -> usize {
+        self.subscribers
+            .get(&TypeId::of::<E>())
+            .and_then(|subscribers| subscribers.downcast_ref::<Subscribers<E>>())
+            .map_or(0, Vec::len)
+    }
+
+    // Drops every subscription, eg when a scene is torn down
+    pub fn clear(&mut self) {
+        self.subscribers.clear();
+        self.queued.clear();
+        self.unsubscribed.clear();
+        self.clear_count += 1;
+    }
+
+    // Delivers to every subscriber before returning. An event emitted from inside
+    // a handler for the same event type is queued instead, to avoid unbounded recursion.
+    pub fn emit<E: 'static>(&mut self, registry: &mut Registry, event: E) {
+        let type_id = TypeId::of::<E>();
+        if self.dispatching.contains(&type_id) {
+            self.queue(event);
+            return;
+        }
+
+        // taken out while dispatching so handlers can use the bus
+        let mut subscribers = std::mem::take(self.subscribers_mut::<E>());
+        let clear_count = self.clear_count;
+        self.dispatching.insert(type_id);
+        for (id, handler) in subscribers.iter_mut() {
+            if self.clear_count != clear_count {
+                break;
+            }
+            if !self.unsubscribed.contains(id) {
+                handler(&event, registry, self);
+            }
+        }
+        self.dispatching.remove(&type_id);
+
+        // keep subscriptions made during dispatch, drop ones removed during it
+        if self.clear_count != clear_count {
+            subscribers.clear();
+        }
+        let added = std::mem::take(self.subscribers_mut::<E>());
+        subscribers.extend(added);
+        subscribers.retain(|(id, _)| !self.unsubscribed.remove(id));
+        *self.subscribers_mut::<E>() = subscribers;
+    }
+
+    // Delivered at the next flush, in the order queued
+    pub fn queue<E: 'static>(&mut self, event: E) {
+        self.queued
+            .push_back(Box::new(move |bus, registry| bus.emit(registry, event)));
+    }
+
+    pub fn queued_count(&self) -> usize {
+        self.queued.len()
+    }
+
+    // Delivers queued events, including ones queued by handlers during the flush
+    pub fn flush(&mut self, registry: &mut Registry) {
+        let mut delivered = 0;
+        while let Some(deliver) = self.queued.pop_front() {
+            if delivered == MAX_EVENTS_PER_FLUSH {
+                self.queued.push_front(deliver);
+                kwarn!(
+                    "Event flush limit reached, {} events left for next frame",
+                    self.queued.len()
+                );
+                return;
+            }
+            deliver(self, registry);
+            delivered += 1;
+        }
+    }
+
+    fn subscribers_mut<E: 'static>(&mut self) -> &mut Subscribers<E> {
+        self.subscribers
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Subscribers::<E>::new()))
+            .downcast_mut::<Subscribers<E>>()
+            .expect("subscribers are stored under their own event's TypeId")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    struct Ping;
+
+    #[test]
+    fn handler_can_unsubscribe_itself() {
+        let mut bus = EventBus::new();
+        let mut registry = Registry::new();
+        let calls = Rc::new(Cell::new(0));
+        let own_id = Rc::new(Cell::new(None));
+        {
+            let (calls, own_id) = (calls.clone(), own_id.clone());
+            own_id
+                .clone()
+                .set(Some(bus.subscribe::<Ping>(move |_, _, bus| {
+                    calls.set(calls.get() + 1);
+                    bus.unsubscribe::<Ping>(own_id.get().unwrap());
+                })));
+        }
+
+        bus.emit(&mut registry, Ping);
+        bus.emit(&mut registry, Ping);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(bus.subscriber_count::<Ping>(), 0);
+    }
+
+    #[test]
+    fn handler_unsubscribed_by_an_earlier_one_is_skipped() {
+        let mut bus = EventBus::new();
+        let mut registry = Registry::new();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let second = Rc::new(Cell::new(None));
+        {
+            let (calls, second) = (calls.clone(), second.clone());
+            bus.subscribe::<Ping>(move |_, _, bus| {
+                calls.borrow_mut().push("first");
+                bus.unsubscribe::<Ping>(second.get().unwrap());
+            });
+        }
+        {
+            let calls = calls.clone();
+            second.set(Some(bus.subscribe::<Ping>(move |_, _, _| {
+                calls.borrow_mut().push("second");
+            })));
+        }
+
+        bus.emit(&mut registry, Ping);
+        assert_eq!(*calls.borrow(), ["first"]);
+        assert_eq!(bus.subscriber_count::<Ping>(), 1);
+    }
+
+    #[test]
+    fn clear_from_a_handler_drops_every_subscription() {
+        let mut bus = EventBus::new();
+        let mut registry = Registry::new();
+        let calls = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let calls = calls.clone();
+            bus.subscribe::<Ping>(move |_, _, bus| {
+                calls.set(calls.get() + 1);
+                bus.clear();
+            });
+        }
+
+        bus.emit(&mut registry, Ping);
+        bus.emit(&mut registry, Ping);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(bus.subscriber_count::<Ping>(), 0);
+    }
+
+    #[test]
+    fn subscription_made_during_dispatch_is_kept() {
+        let mut bus = EventBus::new();
+        let mut registry = Registry::new();
+        let calls = Rc::new(Cell::new(0));
+        {
+            let calls = calls.clone();
+            bus.subscribe::<Ping>(move |_, _, bus| {
+                let calls = calls.clone();
+                bus.subscribe::<Ping>(move |_, _, _| calls.set(calls.get() + 1));
+            });
+        }
+
+        bus.emit(&mut registry, Ping);
+        assert_eq!(calls.get(), 0);
+        assert_eq!(bus.subscriber_count::<Ping>(), 2);
+        bus.emit(&mut registry, Ping);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn same_type_emitted_from_a_handler_is_queued() {
+        let mut bus = EventBus::new();
+        let mut registry = Registry::new();
+        let calls = Rc::new(Cell::new(0));
+        {
+            let calls = calls.clone();
+            bus.subscribe::<Ping>(move |_, registry, bus| {
+                calls.set(calls.get() + 1);
+                if calls.get() == 1 {
+                    bus.emit(registry, Ping);
+                }
+            });
+        }
+
+        bus.emit(&mut registry, Ping);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(bus.queued_count(), 1);
+        bus.flush(&mut registry);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(bus.queued_count(), 0);
+    }
+}
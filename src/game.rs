@@ -1,32 +1,51 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result};
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, render::Canvas, version::revision,
-    video::Window, EventPump,
+    controller::GameController,
+    event::{Event, WindowEvent},
+    hint,
+    image::SaveSurface,
+    pixels::{Color, PixelFormatEnum},
+    render::{Canvas, RenderTarget},
+    surface::Surface,
+    version::revision,
+    video::Window,
+    AudioSubsystem, EventPump, GameControllerSubsystem, VideoSubsystem,
 };
-use std::env;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+use crate::asset_store::{AssetStore, DEFAULT_ASSETS_DIR};
+use crate::audio::AudioManager;
+use crate::camera::{update_camera, Camera};
+use crate::config::GameConfiguration;
 use crate::dsa::FixedSizeQueue;
+use crate::ecs::systems::{
+    AnimationSystem, CollisionSystem, HealthSystem, RenderSystem, RenderTextSystem, TransformSystem,
+};
 use crate::ecs::Registry;
-use crate::logger::{LogLevel, Logger};
-
-const FRAMERATE: u8 = 60;
-const FRAME_LIMIT_MS: f64 = 1000.0 / FRAMERATE as f64;
-const WINDOW_WIDTH: u32 = 800;
-const WINDOW_HEIGHT: u32 = 600;
-
-// Game Loop States
-// eg:
-// [init] => Stopped => [run] => Running => [input: pause] => Paused => [input: stop]
-// => Stopped => [input: pause] => (no effect)Stopped => [input: start/resume] => Resuming => Running => [input: pause]
-// => Paused => [input: unpause] => Running
-pub enum RunState {
-    Stopped, //  when render not running
-    Running,
-    Paused,
-    Resuming, // transient state that marks going from Stopped -> Running
-    Exiting,
-}
+use crate::event_bus::EventBus;
+use crate::input::gamepad::GamepadState;
+use crate::input::{actions, InputMap, InputState};
+use crate::logger::{Logger, DEFAULT_ASYNC_LOG_CAPACITY};
+use crate::text::TextRenderer;
+use crate::{kdbg, kerr, kinfo, kwarn};
+
+mod bitmap_font;
+pub mod debug_overlay;
+pub mod pause_scene;
+pub mod run_state;
+pub mod scene;
+
+pub use pause_scene::PauseScene;
+pub use run_state::{InvalidTransition, RunState, RunStateMachine};
+pub use scene::{Scene, SceneContext, SceneStack};
+
+use debug_overlay::{DebugOverlay, DEBUG_FONT_ID, DEBUG_FONT_PATH};
+use scene::SceneCommand;
+
+const SCREENSHOT_DIR: &str = "screenshots";
 
 #[derive(Debug)]
 pub enum InitError {
@@ -45,38 +64,80 @@ impl std::fmt::Display for InitError {
 
 impl std::error::Error for InitError {}
 
-pub struct GameConfiguration;
+// How a headless run ended, see Game::run_headless
+#[derive(Debug, PartialEq)]
+pub enum HeadlessResult {
+    Completed { frames: u64 }, // ran every requested frame or the stop condition was met
+    TimedOut { frames: u64 },  // hit the frame limit before the stop condition was met
+    Exited { frames: u64 },    // quit requested, eg SIGINT
+}
+
+impl HeadlessResult {
+    pub fn frames(&self) -> u64 {
+        match self {
+            HeadlessResult::Completed { frames }
+            | HeadlessResult::TimedOut { frames }
+            | HeadlessResult::Exited { frames } => *frames,
+        }
+    }
+
+    // process exit code, eg for CI
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HeadlessResult::Completed { .. } | HeadlessResult::Exited { .. } => 0,
+            HeadlessResult::TimedOut { .. } => 1,
+        }
+    }
+}
+
 pub struct Game {
-    pub run_state: RunState,
-    canvas: Canvas<Window>,
+    run_state: RunStateMachine,
+    config: GameConfiguration,
+    canvas: Option<Canvas<Window>>,      // None when headless
+    text_renderer: Option<TextRenderer>, // None when headless or SDL_ttf failed
+    video_subsystem: VideoSubsystem,
+    controller_subsystem: Option<GameControllerSubsystem>, // None if it failed to init
+    controllers: HashMap<u32, GameController>,             // open handles by instance id
     event_pump: EventPump,
     ms_prev_frame: Instant,
     fps: f64,
     fps_queue: FixedSizeQueue,
     is_debug_on: bool,
-    registry: Registry
+    debug_overlay: DebugOverlay,
+    is_screenshot_requested: bool,
+    dt: f64, // seconds the previous frame took
+    scenes: SceneStack,
+    pause_scene: Option<Box<dyn Fn() -> Box<dyn Scene>>>,
+    pause_scene_depth: Option<usize>, // stack depth below the pushed pause scene
+    event_bus: EventBus,
+    registry: Registry,
+    // None if it failed to init, kept open for the mixer. Fields drop in order, so
+    // this stays after registry and the AudioManager closes the mixer first.
+    _audio_subsystem: Option<AudioSubsystem>,
 }
 
 impl Game {
+    // Default configuration, ignores the command line, env and config files
     pub fn new() -> Result<Self, anyhow::Error> {
-        Logger::dbg("INIT start");
+        Self::with_config(GameConfiguration::default())
+    }
 
-        // todo 1. pass config struct
-        // todo 2. let game init/new parse readline
-        // todo 3. pass both, then readline args override config struct
-        // todo 4. read a toml config file that can be overriden by readline
+    // Never reads process args or exits, errors are returned to the caller.
+    // See main.rs for building the config from the command line.
+    pub fn with_config(config: GameConfiguration) -> Result<Self, anyhow::Error> {
+        kdbg!("INIT start");
 
-        let readline_args = parse_readline().unwrap_or_else(|e| {
-            eprintln!("{e}");
-            std::process::exit(1);
-        });
+        config.validate()?;
 
-        match readline_args.log_level {
-            Some(level) => Logger::new(level, None),
-            None => Logger::new(LogLevel::Debug, None),
-        };
+        Logger::new(config.log_filter.clone(), config.log_output.as_deref())?;
 
-        Logger::info(&format!("{}", revision())); // SDL version
+        kinfo!("{}", revision()); // SDL version
+
+        // dummy drivers need no display or sound card, eg CI boxes
+        if config.headless {
+            hint::set("SDL_VIDEODRIVER", "dummy");
+            hint::set("SDL_AUDIODRIVER", "dummy");
+        }
 
         let sdl_context = sdl2::init()
             .map_err(|e| anyhow::anyhow!("{}", e)) // convert init's result error type of String into an anyhow error
@@ -88,46 +149,102 @@ impl Game {
             .map_err(|e| anyhow::anyhow!("{}", e))
             .with_context(|| "Failed to initialize video subsystem".to_owned())?;
 
-        let window_width = WINDOW_WIDTH;
-        let window_height = WINDOW_HEIGHT;
-        let window = video_subsystem
-            .window("kengen", window_width, window_height)
-            .position_centered()
-            .borderless()
-            .build()
-            .map_err(|e| anyhow::anyhow!("{}", e))
-            .with_context(|| "Failed to create window".to_owned())?;
+        let canvas = if config.headless {
+            kinfo!("Running headless, no window");
+            None
+        } else {
+            Some(create_canvas(&video_subsystem, &config)?)
+        };
 
-        let canvas = window
-            .into_canvas()
-            .build()
-            .map_err(|e| anyhow::anyhow!("{}", e))
-            .with_context(|| "Failed to create canvas".to_owned())?;
+        let mut text_renderer = canvas.as_ref().and_then(|canvas| {
+            TextRenderer::new(canvas.texture_creator(), DEFAULT_ASSETS_DIR)
+                .map_err(|e| kwarn!("Text unavailable: {:#}", e))
+                .ok()
+        });
+        // optional, the debug overlay falls back to its bitmap font
+        if let Some(text_renderer) = text_renderer.as_mut() {
+            if Path::new(DEFAULT_ASSETS_DIR).join(DEBUG_FONT_PATH).exists() {
+                if let Err(e) = text_renderer.add_font(DEBUG_FONT_ID, Path::new(DEBUG_FONT_PATH)) {
+                    kwarn!("Debug overlay font unavailable: {:#}", e);
+                }
+            }
+        }
+
+        // not fatal, the game still runs on keyboard and mouse
+        let controller_subsystem = sdl_context
+            .game_controller()
+            .map_err(|e| kwarn!("Gamepads unavailable: {}", e))
+            .ok();
+
+        // not fatal either, AudioManager is then a no-op
+        let audio_subsystem = sdl_context
+            .audio()
+            .map_err(|e| kwarn!("Audio unavailable: {}", e))
+            .ok();
 
         let event_pump = sdl_context
             .event_pump()
             .map_err(|e| anyhow::anyhow!("{}", e))
             .with_context(|| "Failed to create event pump".to_owned())?;
 
-        let registry = Registry::new();
-
-        Logger::dbg("INIT end");
+        // input is a registry resource so systems can read it during update
+        let mut registry = Registry::new();
+        registry.add_resource(InputMap::new(config.input_bindings.clone()));
+        registry.add_resource(InputState::new());
+        registry.add_resource(GamepadState::new(config.gamepad_deadzone));
+        registry.add_system(TransformSystem::new());
+        registry.add_system(CollisionSystem::new());
+        registry.add_system(HealthSystem::new());
+        registry.add_system(AnimationSystem::new());
+        registry.add_system(RenderSystem::new());
+        registry.add_system(RenderTextSystem::new());
+        registry.add_resource(AssetStore::new(DEFAULT_ASSETS_DIR));
+        registry.add_resource(AudioManager::new(
+            audio_subsystem.as_ref(),
+            DEFAULT_ASSETS_DIR,
+        ));
+        registry.add_resource(Camera::new(config.window_width, config.window_height));
+        let fps_queue = FixedSizeQueue::new(config.framerate as usize);
+
+        // Started last, nothing after it can fail, so an error above never leaves the
+        // logging thread running without a Game to stop it on drop
+        if let Some(policy) = config.log_async {
+            Logger::start_async(DEFAULT_ASYNC_LOG_CAPACITY, policy)?;
+        }
 
+        kdbg!("INIT end");
 
         Ok(Self {
-            run_state: RunState::Stopped,
+            run_state: RunStateMachine::new(),
+            config,
             ms_prev_frame: Instant::now(),
             canvas,
+            text_renderer,
+            video_subsystem,
+            controller_subsystem,
+            controllers: HashMap::new(),
             event_pump,
             fps: 0.0,
-            fps_queue: FixedSizeQueue::new(60),
+            fps_queue,
             is_debug_on: false,
-            registry
+            debug_overlay: DebugOverlay::new(),
+            is_screenshot_requested: false,
+            dt: 0.0,
+            scenes: SceneStack::new(),
+            pause_scene: None,
+            pause_scene_depth: None,
+            event_bus: EventBus::new(),
+            registry,
+            _audio_subsystem: audio_subsystem,
         })
     }
 
-    fn setup(&self) {
-        Logger::dbg("SETUP start");
+    fn setup(&mut self) {
+        kdbg!("SETUP start");
+
+        if self.pause_scene.is_none() {
+            self.set_pause_scene(|| Box::new(PauseScene::new()));
+        }
 
         // Add systems that need to be processed
         // registry->AddSystem<MovementSystem>();
@@ -141,94 +258,388 @@ impl Game {
         // tank.AddComponent<RigidBodyComponent>(glm::vec2(40.0, 0.0));
         // tank.AddComponent<SpriteComponent>(10, 10);
 
-        Logger::dbg("SETUP end");
+        kdbg!("SETUP end");
     }
 
     pub fn run(&mut self) -> () {
         self.setup();
-        self.run_state = RunState::Running;
-        Logger::dbg("Game loop running");
+        self.request_run_state(RunState::Running);
+        kdbg!("Game loop running");
         loop {
             self.handle_input();
 
-            match self.run_state {
+            match self.run_state.state() {
                 RunState::Running => {
                     self.update();
+                    self.render();
                 }
                 RunState::Paused => {
-                    // show pause menu
+                    // the pause scene, if set, is on top of the stack
+                    self.render();
                 }
                 RunState::Stopped => {
-                    continue;
+                    // nothing to draw, keep pumping events at the frame rate rather than spinning
                 }
-                RunState::Resuming => self.run_state = RunState::Running,
                 RunState::Exiting => {
                     break;
                 }
             }
-            self.render();
 
             self.handle_tick();
         }
     }
 
+    // Runs the update loop without a window: no render, no frame rate cap.
+    // Stops after `max_frames` or on quit, see run_headless_until for a stop condition.
+    pub fn run_headless(&mut self, max_frames: u64) -> HeadlessResult {
+        match self.run_headless_until(max_frames, |_| false) {
+            HeadlessResult::TimedOut { frames } => HeadlessResult::Completed { frames },
+            result => result,
+        }
+    }
+
+    // `until` is checked after every frame, eg: |game| game.run_state() == RunState::Paused
+    pub fn run_headless_until<F>(&mut self, max_frames: u64, mut until: F) -> HeadlessResult
+    where
+        F: FnMut(&Game) -> bool,
+    {
+        self.setup();
+        self.request_run_state(RunState::Running);
+        kdbg!("Headless loop running for up to {} frames", max_frames);
+        // fixed step, frames aren't paced so wall clock time means nothing here
+        self.dt = 1.0 / self.config.framerate as f64;
+
+        let mut frames = 0;
+        while frames < max_frames {
+            self.handle_input();
+
+            match self.run_state.state() {
+                RunState::Running => self.update(),
+                RunState::Paused | RunState::Stopped => {}
+                RunState::Exiting => return HeadlessResult::Exited { frames },
+            }
+            frames += 1;
+
+            if until(self) {
+                return HeadlessResult::Completed { frames };
+            }
+        }
+        HeadlessResult::TimedOut { frames }
+    }
+
+    pub fn run_state(&self) -> RunState {
+        self.run_state.state()
+    }
+
+    pub fn set_run_state(&mut self, next: RunState) -> Result<(), InvalidTransition> {
+        let prev = self.run_state.state();
+        self.run_state.transition(next)?;
+        self.sync_pause_scene(prev, next);
+        Ok(())
+    }
+
+    // eg to register enter/exit hooks
+    pub fn run_state_machine_mut(&mut self) -> &mut RunStateMachine {
+        &mut self.run_state
+    }
+
+    // for input driven transitions, where an invalid one is just ignored
+    fn request_run_state(&mut self, next: RunState) {
+        if let Err(e) = self.set_run_state(next) {
+            kdbg!("{}", e);
+        }
+    }
+
+    pub fn assets(&self) -> &AssetStore {
+        self.registry
+            .get_resource::<AssetStore>()
+            .expect("AssetStore resource is added in Game::with_config")
+    }
+
+    pub fn assets_mut(&mut self) -> &mut AssetStore {
+        self.registry
+            .get_resource_mut::<AssetStore>()
+            .expect("AssetStore resource is added in Game::with_config")
+    }
+
+    pub fn audio(&self) -> &AudioManager {
+        self.registry
+            .get_resource::<AudioManager>()
+            .expect("AudioManager resource is added in Game::with_config")
+    }
+
+    pub fn audio_mut(&mut self) -> &mut AudioManager {
+        self.registry
+            .get_resource_mut::<AudioManager>()
+            .expect("AudioManager resource is added in Game::with_config")
+    }
+
+    // None when headless, eg to add_font
+    pub fn text_renderer_mut(&mut self) -> Option<&mut TextRenderer> {
+        self.text_renderer.as_mut()
+    }
+
+    // Path is relative to the assets dir. Headless there is no renderer to load
+    // into, sprites then draw as rects.
+    pub fn load_texture(&mut self, id: &str, path: &Path) -> Result<(), anyhow::Error> {
+        let Some(canvas) = self.canvas.as_ref() else {
+            kdbg!("Headless, skipping texture {}", id);
+            return Ok(());
+        };
+        let texture_creator = canvas.texture_creator();
+        self.registry
+            .get_resource_mut::<AssetStore>()
+            .expect("AssetStore resource is added in Game::with_config")
+            .add_texture(&texture_creator, id, path)
+    }
+
+    pub fn event_bus(&self) -> &EventBus {
+        &self.event_bus
+    }
+
+    pub fn event_bus_mut(&mut self) -> &mut EventBus {
+        &mut self.event_bus
+    }
+
+    // * Scene Management
+    pub fn scenes(&self) -> &SceneStack {
+        &self.scenes
+    }
+
+    pub fn push_scene(&mut self, mut scene: Box<dyn Scene>) {
+        kdbg!("Push scene {}", scene.name());
+        let mut ctx = SceneContext::new(
+            &mut self.registry,
+            &mut self.event_bus,
+            self.run_state.state(),
+            self.dt,
+        );
+        scene.setup(&mut ctx);
+        let commands = ctx.into_commands();
+        self.scenes.push(scene);
+        self.apply_scene_commands(commands);
+    }
+
+    pub fn pop_scene(&mut self) {
+        let Some(mut scene) = self.scenes.pop() else {
+            kdbg!("Cannot pop scene, stack is empty");
+            return;
+        };
+        kdbg!("Pop scene {}", scene.name());
+        if self
+            .pause_scene_depth
+            .is_some_and(|depth| self.scenes.len() <= depth)
+        {
+            self.pause_scene_depth = None;
+        }
+        let mut ctx = SceneContext::new(
+            &mut self.registry,
+            &mut self.event_bus,
+            self.run_state.state(),
+            self.dt,
+        );
+        scene.teardown(&mut ctx);
+        let commands = ctx.into_commands();
+        self.apply_scene_commands(commands);
+    }
+
+    pub fn replace_scene(&mut self, scene: Box<dyn Scene>) {
+        self.pop_scene();
+        self.push_scene(scene);
+    }
+
+    // Scene pushed when entering Paused and popped, with anything above it, on leaving.
+    // Defaults to PauseScene.
+    pub fn set_pause_scene(&mut self, make_scene: impl Fn() -> Box<dyn Scene> + 'static) {
+        self.pause_scene = Some(Box::new(make_scene));
+    }
+
+    fn sync_pause_scene(&mut self, prev: RunState, next: RunState) {
+        if next == RunState::Paused {
+            if let Some(make_scene) = self.pause_scene.as_ref() {
+                let scene = make_scene();
+                self.pause_scene_depth = Some(self.scenes.len());
+                self.push_scene(scene);
+            }
+        } else if prev == RunState::Paused {
+            if let Some(depth) = self.pause_scene_depth.take() {
+                while self.scenes.len() > depth {
+                    self.pop_scene();
+                }
+            }
+        }
+    }
+
+    fn apply_scene_commands(&mut self, commands: Vec<SceneCommand>) {
+        for command in commands {
+            match command {
+                SceneCommand::Push(scene) => self.push_scene(scene),
+                SceneCommand::Pop => self.pop_scene(),
+                SceneCommand::Replace(scene) => self.replace_scene(scene),
+                SceneCommand::SetRunState(next) => self.request_run_state(next),
+            }
+        }
+    }
+
+    // top scene only, runs in every state but Exiting
+    fn handle_scene_input(&mut self) {
+        let run_state = self.run_state.state();
+        let Some(scene) = self.scenes.top_mut() else {
+            return;
+        };
+        let mut ctx =
+            SceneContext::new(&mut self.registry, &mut self.event_bus, run_state, self.dt);
+        scene.handle_input(&mut ctx);
+        let commands = ctx.into_commands();
+        self.apply_scene_commands(commands);
+    }
+    pub fn is_headless(&self) -> bool {
+        self.canvas.is_none()
+    }
+
+    pub fn config(&self) -> &GameConfiguration {
+        &self.config
+    }
+
+    pub fn input_map(&self) -> &InputMap {
+        self.registry
+            .get_resource::<InputMap>()
+            .expect("InputMap resource is added in Game::with_config")
+    }
+
+    // eg to rebind from an options menu
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        self.registry
+            .get_resource_mut::<InputMap>()
+            .expect("InputMap resource is added in Game::with_config")
+    }
+
+    pub fn gamepads(&self) -> &GamepadState {
+        self.registry
+            .get_resource::<GamepadState>()
+            .expect("GamepadState resource is added in Game::with_config")
+    }
+
+    pub fn input(&self) -> &InputState {
+        self.registry
+            .get_resource::<InputState>()
+            .expect("InputState resource is added in Game::with_config")
+    }
+
+    // TextInput events only arrive while on, eg turn on while a text field has focus
+    pub fn set_text_input(&self, is_on: bool) {
+        let text_input = self.video_subsystem.text_input();
+        if is_on {
+            text_input.start();
+        } else {
+            text_input.stop();
+        }
+    }
+
     fn handle_input(&mut self) {
-        for event in self.event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        let is_quit_requested = events.iter().any(|e| matches!(e, Event::Quit { .. }));
+        if let Some(gamepads) = self.registry.get_resource_mut::<GamepadState>() {
+            gamepads.begin_frame();
+        }
+        self.handle_controller_hotplug(&events);
+
+        if let Some(input_state) = self.registry.get_resource_mut::<InputState>() {
+            input_state.begin_frame();
+            for event in &events {
+                input_state.handle_event(event);
+            }
+        }
+        if let Some(gamepads) = self.registry.get_resource_mut::<GamepadState>() {
+            for event in &events {
+                gamepads.handle_event(event);
+            }
+        }
+        if let Some(camera) = self.registry.get_resource_mut::<Camera>() {
+            for event in &events {
+                if let Event::Window {
+                    win_event: WindowEvent::SizeChanged(width, height),
                     ..
-                } => {
-                    // if game already stopped, then quit, eg takes 2 ESCs to exit game
-                    match self.run_state {
-                        RunState::Stopped => {
-                            Logger::info("Game exiting");
-                            self.run_state = RunState::Exiting;
-                        }
-                        _ => {
-                            Logger::info("Game stopped");
-                            self.run_state = RunState::Stopped;
-                        }
-                    }
+                } = *event
+                {
+                    camera.set_viewport(width.max(0) as u32, height.max(0) as u32);
                 }
-                Event::KeyDown {
-                    keycode: Some(Keycode::P),
-                    ..
-                } => match self.run_state {
-                    RunState::Paused => {
-                        Logger::info("Game unpaused");
-                        self.run_state = RunState::Running;
-                    }
-                    RunState::Running => {
-                        Logger::info("Game paused");
-                        self.run_state = RunState::Paused;
+            }
+        }
+        let Some(input_map) = self.registry.get_resource_mut::<InputMap>() else {
+            return;
+        };
+        for event in &events {
+            input_map.handle_event(event);
+        }
+        input_map.end_frame();
+
+        let is_stop_pressed = input_map.just_pressed(actions::STOP);
+        let is_pause_pressed = input_map.just_pressed(actions::PAUSE);
+        let is_toggle_run_pressed = input_map.just_pressed(actions::TOGGLE_RUN);
+        let is_toggle_debug_pressed = input_map.just_pressed(actions::TOGGLE_DEBUG);
+        let is_screenshot_pressed = input_map.just_pressed(actions::SCREENSHOT);
+
+        for &next in stop_path(
+            self.run_state.state(),
+            is_quit_requested,
+            is_stop_pressed,
+            self.config.headless,
+        ) {
+            self.request_run_state(next);
+        }
+        if is_pause_pressed {
+            match self.run_state.state() {
+                RunState::Paused => self.request_run_state(RunState::Running),
+                _ => self.request_run_state(RunState::Paused),
+            }
+        }
+        if is_toggle_run_pressed {
+            match self.run_state.state() {
+                RunState::Stopped => self.request_run_state(RunState::Running),
+                _ => self.request_run_state(RunState::Stopped),
+            }
+        }
+        if is_toggle_debug_pressed {
+            self.is_debug_on = !self.is_debug_on;
+            let mode = if self.is_debug_on { "ON" } else { "OFF" };
+            kdbg!("Debug mode {}", mode);
+        }
+        if is_screenshot_pressed {
+            // taken in render, between drawing and present
+            self.is_screenshot_requested = true;
+        }
+
+        if self.run_state.state() != RunState::Exiting {
+            self.handle_scene_input();
+        }
+    }
+
+    // SDL sends ControllerDeviceAdded for pads present at startup too.
+    // Opening a pad is what makes SDL send its button and axis events.
+    fn handle_controller_hotplug(&mut self, events: &[Event]) {
+        let Some(subsystem) = self.controller_subsystem.as_ref() else {
+            return;
+        };
+        for event in events {
+            match *event {
+                Event::ControllerDeviceAdded { which, .. } => match subsystem.open(which) {
+                    Ok(controller) => {
+                        let id = controller.instance_id();
+                        let name = controller.name();
+                        kinfo!("Gamepad connected: {} (id {})", name, id);
+                        if let Some(gamepads) = self.registry.get_resource_mut::<GamepadState>() {
+                            gamepads.connect(id, &name);
+                        }
+                        self.controllers.insert(id, controller);
                     }
-                    _ => {}
+                    Err(e) => kwarn!("Failed to open gamepad {}: {}", which, e),
                 },
-                Event::KeyDown {
-                    keycode: Some(Keycode::Semicolon),
-                    ..
-                } => match self.run_state {
-                    RunState::Stopped => {
-                        Logger::info("Game resuming");
-                        self.run_state = RunState::Resuming;
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    let was_open = self.controllers.remove(&which).is_some();
+                    if was_open {
+                        kinfo!("Gamepad disconnected (id {})", which);
                     }
-                    RunState::Paused | RunState::Running => {
-                        Logger::info("Game stopped");
-                        self.run_state = RunState::Stopped;
-                    }
-                    _ => {
-                        Logger::dbg("Cannot stop game while it is in process of resuming");
-                    }
-                },
-                Event::KeyDown {
-                    keycode: Some(Keycode::D),
-                    ..
-                } => {
-                    self.is_debug_on = !self.is_debug_on;
-                    let mode = if self.is_debug_on { "ON" } else { "OFF" };
-                    Logger::dbg(&format!("Debug mode {}", mode));
                 }
                 _ => {}
             }
@@ -236,98 +647,287 @@ impl Game {
     }
 
     fn handle_tick(&mut self) {
-        let time_to_wait: f64 = FRAME_LIMIT_MS
+        let frame_limit_ms = self.config.frame_limit_ms();
+        let time_to_wait: f64 = frame_limit_ms
             - Instant::now()
                 .duration_since(self.ms_prev_frame)
                 .as_millis() as f64;
 
         // fixed frame rate: if below threshold MILLISECS_PER_FRAME then sleep
-        if time_to_wait > 0.0 && time_to_wait <= FRAME_LIMIT_MS {
+        if time_to_wait > 0.0 && time_to_wait <= frame_limit_ms {
             let sleep_duration = Duration::new(0, (time_to_wait * 1000000.0) as u32);
             ::std::thread::sleep(sleep_duration);
             if sleep_duration.as_millis() <= 2 {
-                Logger::info(&format!(
+                kinfo!(
                     "Frames getting tight: sleeping {:?}ms",
                     sleep_duration.as_millis()
-                ));
+                );
             }
         }
 
         let dt = Instant::now().duration_since(self.ms_prev_frame);
         self.ms_prev_frame = Instant::now();
+        // dt to millis is u128
+        // the queue holds frame times in ms
         self.fps_queue
-            .push(dt.to_owned().as_millis().try_into().unwrap()); // dt to millis is u128
-        self.fps = self.fps_queue.avg().unwrap_or(0f64);
+            .push(dt.to_owned().as_millis().try_into().unwrap());
+        self.fps = self
+            .fps_queue
+            .avg()
+            .filter(|frame_ms| *frame_ms > 0.0)
+            .map_or(0.0, |frame_ms| 1000.0 / frame_ms);
+        self.dt = dt.as_secs_f64();
     }
 
     pub fn update(&mut self) {
+        let run_state = self.run_state.state();
+        if let Some(scene) = self.scenes.top_mut() {
+            let mut ctx =
+                SceneContext::new(&mut self.registry, &mut self.event_bus, run_state, self.dt);
+            scene.update(&mut ctx);
+            let commands = ctx.into_commands();
+            self.apply_scene_commands(commands);
+        }
+
+        // entities created or killed since the last frame join or leave their systems
+        self.registry.update();
+        self.registry.update_systems(&mut self.event_bus, self.dt);
+        update_camera(&mut self.registry);
 
-        // TODO Update Systems
-        // TODO Update Registry
+        // queued events are delivered once everything for the frame has run
+        self.event_bus.flush(&mut self.registry);
     }
 
     pub fn render(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 255, 0));
-        self.canvas.clear();
-        self.canvas.present();
+        let Some(canvas) = self.canvas.as_mut() else {
+            return;
+        };
+        draw_frame(canvas, &self.registry);
+        if let (Some(text_renderer), Some(render_text_system)) = (
+            self.text_renderer.as_mut(),
+            self.registry.get_system::<RenderTextSystem>(),
+        ) {
+            render_text_system.render(&self.registry, canvas, text_renderer);
+        }
+        for scene in self.scenes.visible_mut() {
+            scene.render(&self.registry, canvas);
+        }
+        if self.is_debug_on {
+            self.debug_overlay.draw(
+                canvas,
+                self.text_renderer.as_mut(),
+                &self.registry,
+                &self.fps_queue,
+                self.fps,
+                self.config.frame_limit_ms(),
+            );
+        }
+
+        // read back before present, the back buffer is undefined afterwards
+        if self.is_screenshot_requested {
+            self.is_screenshot_requested = false;
+            let path = screenshot_path();
+            match save_canvas_png(canvas, &path) {
+                Ok(()) => kinfo!("Screenshot saved to {}", path.display()),
+                Err(e) => kerr!("Screenshot failed: {e:#}"),
+            }
+        }
+        canvas.present();
+        if let Some(text_renderer) = self.text_renderer.as_mut() {
+            text_renderer.end_frame();
+        }
+    }
+
+    // Draws a frame with the software renderer, needs no window or GPU,
+    // eg for headless screenshots or comparing against a golden image
+    pub fn render_to_surface(&self) -> Result<Surface<'static>, anyhow::Error> {
+        let surface = Surface::new(
+            self.config.window_width,
+            self.config.window_height,
+            PixelFormatEnum::RGB888,
+        )
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| "Failed to create offscreen surface".to_owned())?;
+        let mut canvas = surface
+            .into_canvas()
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| "Failed to create offscreen canvas".to_owned())?;
+
+        draw_frame(&mut canvas, &self.registry);
+        Ok(canvas.into_surface())
+    }
+
+    pub fn save_screenshot(&self, path: &Path) -> Result<(), anyhow::Error> {
+        create_parent_dir(path)?;
+        self.render_to_surface()?
+            .save(path)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to save screenshot {}", path.display()))
     }
     pub fn process_input() -> () {}
     pub fn destroy(&self) {
-        Logger::dbg("Destroy game");
+        kdbg!("Destroy game");
     }
 }
 
 impl Drop for Game {
     fn drop(&mut self) {
-        Logger::dbg("Drop game");
+        kdbg!("Drop game");
+        // stopping drains the logging thread, so the next Game can start its own
+        if self.config.log_async.is_some() {
+            Logger::stop_async();
+        } else {
+            Logger::flush();
+        }
     }
 }
 
-struct ReadlineArgs {
-    log_level: Option<LogLevel>,
-    log_output: Option<String>,
+// Run states a frame's stop input steps through.
+// If the game is already stopped it exits, eg takes 2 ESCs to exit game.
+// A headless quit (SIGINT) exits right away, nothing else would end the run.
+fn stop_path(
+    state: RunState,
+    is_quit_requested: bool,
+    is_stop_pressed: bool,
+    is_headless: bool,
+) -> &'static [RunState] {
+    if !is_quit_requested && !is_stop_pressed {
+        return &[];
+    }
+    match state {
+        RunState::Exiting => &[],
+        RunState::Stopped => &[RunState::Exiting],
+        _ if is_quit_requested && is_headless => &[RunState::Stopped, RunState::Exiting],
+        _ => &[RunState::Stopped],
+    }
 }
 
-fn parse_readline() -> Result<ReadlineArgs, anyhow::Error> {
-    // Usage: kengen [--loglevel | -l <LogLevel word>] [--logoutput | -o <log_file>]
-    let mut args = env::args().peekable();
-    let mut readline_args = ReadlineArgs {
-        log_level: None,
-        log_output: None,
-    };
-
-    // if malformed arg value, throw
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "-l" | "--loglevel" => {
-                if let Some(log_level_arg) = args.next() {
-                    readline_args.log_level = Some(parse_log_level_value(&log_level_arg)?);
-                } else {
-                    return Err(anyhow!("Missing argument for log level"));
-                }
-            }
-            "-o" | "--logoutput" => {
-                if let Some(log_output_arg) = args.next() {
-                    readline_args.log_output = Some(log_output_arg.to_owned());
-                } else {
-                    return Err(anyhow!("Missing argument for log output"));
-                }
+fn create_canvas(
+    video_subsystem: &VideoSubsystem,
+    config: &GameConfiguration,
+) -> Result<Canvas<Window>, anyhow::Error> {
+    let mut window_builder = video_subsystem.window(
+        &config.window_title,
+        config.window_width,
+        config.window_height,
+    );
+    window_builder.position_centered();
+    if config.borderless {
+        window_builder.borderless();
+    }
+    let window = window_builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| "Failed to create window".to_owned())?;
+
+    let mut canvas_builder = window.into_canvas();
+    if config.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    canvas_builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| "Failed to create canvas".to_owned())
+}
+
+// Everything drawn each frame, generic so it can target the window or an offscreen surface
+fn draw_frame<T: RenderTarget>(canvas: &mut Canvas<T>, registry: &Registry) {
+    canvas.set_draw_color(Color::RGB(0, 255, 0));
+    canvas.clear();
+    if let Some(render_system) = registry.get_system::<RenderSystem>() {
+        render_system.render(registry, canvas);
+    }
+}
+
+// eg: screenshots/kengen-20231018-142501.123.png
+fn screenshot_path() -> PathBuf {
+    let time = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+    Path::new(SCREENSHOT_DIR).join(format!("kengen-{time}.png"))
+}
+
+fn create_parent_dir(path: &Path) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+fn save_canvas_png(canvas: &Canvas<Window>, path: &Path) -> Result<(), anyhow::Error> {
+    let (width, height) = canvas.output_size().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let format = PixelFormatEnum::RGB24;
+    let mut pixels = canvas
+        .read_pixels(None, format)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| "Failed to read window pixels".to_owned())?;
+    let pitch = format.byte_size_of_pixels(width as usize) as u32;
+    let surface = Surface::from_data(&mut pixels, width, height, pitch, format)
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    create_parent_dir(path)?;
+    surface
+        .save(path)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| format!("Failed to save {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_quit(events: &[Event]) -> bool {
+        events.iter().any(|e| matches!(e, Event::Quit { .. }))
+    }
+
+    // mirrors the Exiting arm of run_headless_until
+    fn headless_frame(run_state: &mut RunStateMachine, events: &[Event]) -> Option<HeadlessResult> {
+        for &next in stop_path(run_state.state(), is_quit(events), false, true) {
+            run_state.transition(next).unwrap();
+        }
+        (run_state.state() == RunState::Exiting).then_some(HeadlessResult::Exited { frames: 1 })
+    }
+
+    #[test]
+    fn headless_quit_exits_from_any_state() {
+        for from in [RunState::Running, RunState::Paused, RunState::Stopped] {
+            let mut run_state = RunStateMachine::new();
+            if from != RunState::Stopped {
+                run_state.transition(RunState::Running).unwrap();
             }
-            _ => {
-                println!("Invalid option: {}", arg);
+            if from == RunState::Paused {
+                run_state.transition(RunState::Paused).unwrap();
             }
+            let result = headless_frame(&mut run_state, &[Event::Quit { timestamp: 0 }]);
+            assert_eq!(
+                result,
+                Some(HeadlessResult::Exited { frames: 1 }),
+                "{from:?}"
+            );
         }
     }
-    Ok(readline_args)
-}
 
-fn parse_log_level_value(s: &str) -> Result<LogLevel, anyhow::Error> {
-    match s {
-        "debug" => Ok(LogLevel::Debug),
-        "info" => Ok(LogLevel::Info),
-        "warning" => Ok(LogLevel::Warning),
-        "error" => Ok(LogLevel::Error),
-        "critical" => Ok(LogLevel::Critical),
-        x => Err(anyhow!("Malformed or empty log level value: {x}")),
+    #[test]
+    fn headless_run_keeps_going_without_a_quit() {
+        let mut run_state = RunStateMachine::new();
+        run_state.transition(RunState::Running).unwrap();
+        assert_eq!(headless_frame(&mut run_state, &[]), None);
+        assert_eq!(run_state.state(), RunState::Running);
+    }
+
+    #[test]
+    fn windowed_stop_takes_two_presses_to_exit() {
+        assert_eq!(
+            stop_path(RunState::Running, false, true, false),
+            [RunState::Stopped]
+        );
+        assert_eq!(
+            stop_path(RunState::Running, true, false, false),
+            [RunState::Stopped]
+        );
+        assert_eq!(
+            stop_path(RunState::Stopped, false, true, false),
+            [RunState::Exiting]
+        );
+        assert!(stop_path(RunState::Exiting, true, true, false).is_empty());
     }
 }
@@ -0,0 +1,227 @@
+use nalgebra::Vector2;
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{BlendMode, Canvas},
+    video::Window,
+};
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::dsa::FixedSizeQueue;
+use crate::ecs::components::{BoxColliderComponent, TextLabelComponent};
+use crate::ecs::systems::{world_transform, Aabb, CollisionSystem};
+use crate::ecs::{Registry, System};
+use crate::kdbg;
+use crate::logger::{LogLevel, Logger};
+use crate::text::TextRenderer;
+
+use super::bitmap_font;
+
+// Overlay text is drawn with the font added under this id, see TextRenderer::add_font.
+// Game adds DEBUG_FONT_PATH in the assets dir when it exists, otherwise the
+// built in bitmap font is used.
+pub const DEBUG_FONT_ID: &str = "debug";
+pub const DEBUG_FONT_PATH: &str = "fonts/debug.ttf";
+const DEBUG_FONT_SIZE: u16 = 14;
+const BITMAP_FONT_SCALE: u32 = 2;
+const RECENT_LOG_LINES: usize = 5;
+
+const MARGIN: i32 = 8;
+const GRAPH_WIDTH: u32 = 180;
+const GRAPH_HEIGHT: u32 = 60;
+
+const PANEL_COLOR: Color = Color::RGBA(0, 0, 0, 160);
+const TEXT_COLOR: Color = Color::WHITE;
+const FRAME_OK_COLOR: Color = Color::RGB(80, 220, 80);
+const FRAME_SLOW_COLOR: Color = Color::RGB(230, 60, 60);
+const TARGET_LINE_COLOR: Color = Color::RGB(240, 220, 60);
+const COLLIDER_COLOR: Color = Color::RGB(255, 0, 255);
+
+// (bar, frame ms) per frame, oldest to newest, right aligned in area. max_ms
+// fills the height, slower frames are clipped to it.
+pub fn frame_time_bars(frame_times_ms: &[u64], area: Rect, max_ms: f64) -> Vec<(Rect, u64)> {
+    if frame_times_ms.is_empty() || max_ms <= 0.0 {
+        return Vec::new();
+    }
+    let bar_width = (area.width() / frame_times_ms.len() as u32).max(1);
+    let fits = (area.width() / bar_width) as usize;
+    let shown = &frame_times_ms[frame_times_ms.len().saturating_sub(fits)..];
+    shown
+        .iter()
+        .enumerate()
+        .filter(|(_, ms)| **ms > 0)
+        .map(|(index, &ms)| {
+            let height = ((ms as f64 / max_ms).min(1.0) * area.height() as f64).ceil() as u32;
+            let x = area.right() - ((shown.len() - index) as u32 * bar_width) as i32;
+            let bar = Rect::new(x, area.bottom() - height as i32, bar_width, height.max(1));
+            (bar, ms)
+        })
+        .collect()
+}
+
+pub fn debug_lines(
+    fps: f64,
+    frame_ms: f64,
+    entity_count: usize,
+    system_timings: &[(String, Duration)],
+) -> Vec<String> {
+    let mut lines = vec![
+        format!("FPS {fps:.1} ({frame_ms:.1} ms)"),
+        format!("Entities {entity_count}"),
+    ];
+    lines.extend(
+        system_timings
+            .iter()
+            .map(|(name, time)| format!("{name} {:.3} ms", time.as_secs_f64() * 1000.0)),
+    );
+    lines
+}
+
+// Log lines are cut to max_chars, they carry the time and call site
+pub fn recent_log_lines(max_chars: usize) -> Vec<String> {
+    Logger::recent(LogLevel::Info, RECENT_LOG_LINES)
+        .into_iter()
+        .map(|record| record.line.chars().take(max_chars).collect())
+        .collect()
+}
+
+// Drawn over everything while debug is on, toggled with actions::TOGGLE_DEBUG
+#[derive(Default)]
+pub struct DebugOverlay {}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        text_renderer: Option<&mut TextRenderer>,
+        registry: &Registry,
+        frame_times: &FixedSizeQueue,
+        fps: f64,
+        target_frame_ms: f64,
+    ) {
+        draw_collider_outlines(canvas, registry);
+        canvas.set_blend_mode(BlendMode::Blend);
+        draw_frame_time_graph(canvas, frame_times, target_frame_ms);
+        self.draw_stats(canvas, text_renderer, registry, frame_times, fps);
+        canvas.set_blend_mode(BlendMode::None);
+    }
+
+    fn draw_stats(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        text_renderer: Option<&mut TextRenderer>,
+        registry: &Registry,
+        frame_times: &FixedSizeQueue,
+        fps: f64,
+    ) {
+        let (width, _) = canvas.output_size().unwrap_or((0, 0));
+        let max_chars = (width.saturating_sub(2 * MARGIN as u32)
+            / (bitmap_font::ADVANCE * BITMAP_FONT_SCALE)) as usize;
+        let mut lines = debug_lines(
+            fps,
+            frame_times.avg().unwrap_or(0.0),
+            registry.entity_count(),
+            registry.system_timings(),
+        );
+        let recent_log = recent_log_lines(max_chars);
+        if !recent_log.is_empty() {
+            lines.push(String::new());
+            lines.extend(recent_log);
+        }
+        let text = lines.join("\n");
+
+        if let Some(text_renderer) = text_renderer.filter(|text| text.has_font(DEBUG_FONT_ID)) {
+            let label = TextLabelComponent::new(&text, DEBUG_FONT_ID, DEBUG_FONT_SIZE)
+                .with_color(TEXT_COLOR);
+            let position = Vector2::new(MARGIN as f32, MARGIN as f32);
+            // the stats change every frame, caching them would only churn the cache
+            match text_renderer.draw_label_uncached(canvas, &label, position) {
+                Ok(()) => return,
+                Err(e) => kdbg!("Failed to draw debug overlay text: {:#}", e),
+            }
+        }
+
+        let (text_width, text_height) = bitmap_font::text_size(&text, BITMAP_FONT_SCALE);
+        let padding = BITMAP_FONT_SCALE as i32 * 2;
+        canvas.set_draw_color(PANEL_COLOR);
+        let _ = canvas.fill_rect(Rect::new(
+            MARGIN - padding,
+            MARGIN - padding,
+            text_width + 2 * padding as u32,
+            text_height + 2 * padding as u32,
+        ));
+        bitmap_font::draw_text(canvas, &text, MARGIN, MARGIN, BITMAP_FONT_SCALE, TEXT_COLOR);
+    }
+}
+
+// top right corner, with a line at the frame rate's target frame time
+fn draw_frame_time_graph(
+    canvas: &mut Canvas<Window>,
+    frame_times: &FixedSizeQueue,
+    target_frame_ms: f64,
+) {
+    let (width, _) = canvas.output_size().unwrap_or((0, 0));
+    let area = Rect::new(
+        width as i32 - MARGIN - GRAPH_WIDTH as i32,
+        MARGIN,
+        GRAPH_WIDTH,
+        GRAPH_HEIGHT,
+    );
+    let samples: Vec<u64> = frame_times.iter().copied().collect();
+    let slowest = samples.iter().copied().max().unwrap_or(0) as f64;
+    let max_ms = slowest.max(target_frame_ms * 2.0);
+
+    canvas.set_draw_color(PANEL_COLOR);
+    let _ = canvas.fill_rect(area);
+    for (bar, ms) in frame_time_bars(&samples, area, max_ms) {
+        let color = if ms as f64 > target_frame_ms {
+            FRAME_SLOW_COLOR
+        } else {
+            FRAME_OK_COLOR
+        };
+        canvas.set_draw_color(color);
+        let _ = canvas.fill_rect(bar);
+    }
+
+    let target_y =
+        area.bottom() - ((target_frame_ms / max_ms) * area.height() as f64).round() as i32;
+    canvas.set_draw_color(TARGET_LINE_COLOR);
+    let _ = canvas.draw_line((area.left(), target_y), (area.right() - 1, target_y));
+}
+
+fn draw_collider_outlines(canvas: &mut Canvas<Window>, registry: &Registry) {
+    let Some(collision_system) = registry.get_system::<CollisionSystem>() else {
+        return;
+    };
+    let camera = match registry.get_resource::<Camera>() {
+        Some(camera) => camera.clone(),
+        None => {
+            let (width, height) = canvas.output_size().unwrap_or((0, 0));
+            Camera::new(width, height)
+        }
+    };
+    canvas.set_draw_color(COLLIDER_COLOR);
+    for &entity in collision_system.base().get_system_entities() {
+        let (Some(transform), Some(collider)) = (
+            world_transform(registry, entity),
+            registry.get_component::<BoxColliderComponent>(entity),
+        ) else {
+            continue;
+        };
+        let aabb = Aabb::from_collider(&transform, collider);
+        let min = camera.world_to_screen(aabb.min);
+        let max = camera.world_to_screen(aabb.max);
+        let outline = Rect::new(
+            min.x.floor() as i32,
+            min.y.floor() as i32,
+            (max.x - min.x).ceil().max(1.0) as u32,
+            (max.y - min.y).ceil().max(1.0) as u32,
+        );
+        let _ = canvas.draw_rect(outline);
+    }
+}
@@ -0,0 +1,82 @@
+use sdl2::{
+    pixels::Color,
+    render::{BlendMode, Canvas},
+    video::Window,
+};
+
+use crate::ecs::Registry;
+
+use super::bitmap_font;
+use super::scene::Scene;
+
+const TEXT: &str = "PAUSED";
+const TEXT_SCALE: u32 = 6;
+const DIM_COLOR: Color = Color::RGBA(0, 0, 0, 140);
+const TEXT_COLOR: Color = Color::WHITE;
+
+// Default pause overlay, Game installs it unless the game set its own with
+// set_pause_scene. Dims whatever is below it and draws "PAUSED" in the middle.
+pub struct PauseScene;
+
+impl PauseScene {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for PauseScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene for PauseScene {
+    fn name(&self) -> &str {
+        "pause"
+    }
+
+    fn render(&mut self, _registry: &Registry, canvas: &mut Canvas<Window>) {
+        let Ok((width, height)) = canvas.output_size() else {
+            return;
+        };
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(DIM_COLOR);
+        let _ = canvas.fill_rect(None);
+        canvas.set_blend_mode(BlendMode::None);
+
+        let (x, y) = text_origin((width, height));
+        bitmap_font::draw_text(canvas, TEXT, x, y, TEXT_SCALE, TEXT_COLOR);
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+// top left of the text so it is centered in the output
+fn text_origin((width, height): (u32, u32)) -> (i32, i32) {
+    let (text_width, text_height) = bitmap_font::text_size(TEXT, TEXT_SCALE);
+    (
+        (width as i32 - text_width as i32) / 2,
+        (height as i32 - text_height as i32) / 2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_an_overlay() {
+        assert!(PauseScene::new().is_overlay());
+        assert_eq!(PauseScene::new().name(), "pause");
+    }
+
+    #[test]
+    fn text_is_centered() {
+        let (text_width, text_height) = bitmap_font::text_size(TEXT, TEXT_SCALE);
+        let (x, y) = text_origin((800, 600));
+        assert_eq!(x * 2 + text_width as i32, 800);
+        assert_eq!(y * 2 + text_height as i32, 600);
+    }
+}
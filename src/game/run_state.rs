@@ -0,0 +1,188 @@
+use crate::kinfo;
+
+// Game loop run states, see diagrams/run-states.md
+//
+// [*] => Stopped => Running <=> Paused
+//        Stopped <= Running, Paused
+//        Stopped => Exiting => [*]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Stopped, // no update or render, still pumps events and sleeps
+    Running,
+    Paused, // no update, still renders
+    Exiting,
+}
+
+impl RunState {
+    pub fn can_transition_to(self, next: RunState) -> bool {
+        matches!(
+            (self, next),
+            (RunState::Stopped, RunState::Running)
+                | (RunState::Stopped, RunState::Exiting)
+                | (RunState::Running, RunState::Paused)
+                | (RunState::Running, RunState::Stopped)
+                | (RunState::Paused, RunState::Running)
+                | (RunState::Paused, RunState::Stopped)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidTransition {
+    pub from: RunState,
+    pub to: RunState,
+}
+
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid run state transition: {:?} -> {:?}",
+            self.from, self.to
+        )
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+// Called with (from, to)
+pub type TransitionHook = Box<dyn FnMut(RunState, RunState)>;
+
+// Owns the current RunState and only allows the transitions in the diagram.
+// Exit hooks of the old state run before enter hooks of the new one.
+pub struct RunStateMachine {
+    state: RunState,
+    enter_hooks: Vec<(RunState, TransitionHook)>,
+    exit_hooks: Vec<(RunState, TransitionHook)>,
+}
+
+impl RunStateMachine {
+    pub fn new() -> Self {
+        Self {
+            state: RunState::Stopped,
+            enter_hooks: Vec::new(),
+            exit_hooks: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn transition(&mut self, next: RunState) -> Result<(), InvalidTransition> {
+        let prev = self.state;
+        if !prev.can_transition_to(next) {
+            return Err(InvalidTransition {
+                from: prev,
+                to: next,
+            });
+        }
+
+        for (state, hook) in self.exit_hooks.iter_mut() {
+            if *state == prev {
+                hook(prev, next);
+            }
+        }
+        self.state = next;
+        kinfo!("Run state {:?} -> {:?}", prev, next);
+        for (state, hook) in self.enter_hooks.iter_mut() {
+            if *state == next {
+                hook(prev, next);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn on_enter(&mut self, state: RunState, hook: impl FnMut(RunState, RunState) + 'static) {
+        self.enter_hooks.push((state, Box::new(hook)));
+    }
+
+    pub fn on_exit(&mut self, state: RunState, hook: impl FnMut(RunState, RunState) + 'static) {
+        self.exit_hooks.push((state, Box::new(hook)));
+    }
+}
+
+impl Default for RunStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const STATES: [RunState; 4] = [
+        RunState::Stopped,
+        RunState::Running,
+        RunState::Paused,
+        RunState::Exiting,
+    ];
+
+    #[test]
+    fn only_the_diagram_transitions_are_allowed() {
+        let allowed = [
+            (RunState::Stopped, RunState::Running),
+            (RunState::Stopped, RunState::Exiting),
+            (RunState::Running, RunState::Paused),
+            (RunState::Running, RunState::Stopped),
+            (RunState::Paused, RunState::Running),
+            (RunState::Paused, RunState::Stopped),
+        ];
+        for from in STATES {
+            for to in STATES {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_transition_keeps_the_state() {
+        let mut machine = RunStateMachine::new();
+        let error = machine.transition(RunState::Paused).unwrap_err();
+        assert_eq!(
+            (error.from, error.to),
+            (RunState::Stopped, RunState::Paused)
+        );
+        assert_eq!(machine.state(), RunState::Stopped);
+    }
+
+    #[test]
+    fn exit_hooks_run_before_enter_hooks() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = RunStateMachine::new();
+        // registered enter first to show order doesn't come from registration
+        let log = calls.clone();
+        machine.on_enter(RunState::Running, move |from, to| {
+            log.borrow_mut().push(("enter running", from, to))
+        });
+        let log = calls.clone();
+        machine.on_exit(RunState::Stopped, move |from, to| {
+            log.borrow_mut().push(("exit stopped", from, to))
+        });
+        let log = calls.clone();
+        machine.on_enter(RunState::Paused, move |from, to| {
+            log.borrow_mut().push(("enter paused", from, to))
+        });
+
+        machine.transition(RunState::Running).unwrap();
+        assert_eq!(
+            *calls.borrow(),
+            [
+                ("exit stopped", RunState::Stopped, RunState::Running),
+                ("enter running", RunState::Stopped, RunState::Running),
+            ]
+        );
+
+        // hooks don't run for a rejected transition
+        calls.borrow_mut().clear();
+        let _ = machine.transition(RunState::Exiting);
+        assert!(calls.borrow().is_empty());
+    }
+}
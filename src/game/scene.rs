@@ -0,0 +1,197 @@
+use sdl2::{render::Canvas, video::Window};
+
+use crate::ecs::Registry;
+use crate::event_bus::EventBus;
+use crate::game::RunState;
+
+// A game state on the scene stack, eg title menu, level, pause overlay.
+// Only the top scene gets input and update, rendering starts from the topmost
+// opaque scene so overlays draw over whatever is below them.
+pub trait Scene {
+    fn name(&self) -> &str;
+
+    // called when pushed, eg create the level's entities
+    fn setup(&mut self, _ctx: &mut SceneContext) {}
+
+    // called when popped or replaced
+    fn teardown(&mut self, _ctx: &mut SceneContext) {}
+
+    // every frame, including while Paused, read input through the registry's InputMap
+    fn handle_input(&mut self, _ctx: &mut SceneContext) {}
+
+    // only while Running
+    fn update(&mut self, _ctx: &mut SceneContext) {}
+
+    fn render(&mut self, _registry: &Registry, _canvas: &mut Canvas<Window>) {}
+
+    // true to render the scenes below first, eg a pause menu over the level
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+pub(crate) enum SceneCommand {
+    Push(Box<dyn Scene>),
+    Pop,
+    Replace(Box<dyn Scene>),
+    SetRunState(RunState),
+}
+
+// What a scene can see and do during its callbacks. Stack changes and run
+// state requests are queued and applied once the callback returns.
+pub struct SceneContext<'a> {
+    pub registry: &'a mut Registry,
+    pub events: &'a mut EventBus, // emit for now, queue for the end of the frame
+    pub run_state: RunState,
+    pub dt: f64, // seconds since the previous frame
+    commands: Vec<SceneCommand>,
+}
+
+impl<'a> SceneContext<'a> {
+    pub(crate) fn new(
+        registry: &'a mut Registry,
+        events: &'a mut EventBus,
+        run_state: RunState,
+        dt: f64,
+    ) -> Self {
+        Self {
+            registry,
+            events,
+            run_state,
+            dt,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn push_scene(&mut self, scene: Box<dyn Scene>) {
+        self.commands.push(SceneCommand::Push(scene));
+    }
+
+    pub fn pop_scene(&mut self) {
+        self.commands.push(SceneCommand::Pop);
+    }
+
+    pub fn replace_scene(&mut self, scene: Box<dyn Scene>) {
+        self.commands.push(SceneCommand::Replace(scene));
+    }
+
+    // invalid transitions are ignored, see RunState::can_transition_to
+    pub fn set_run_state(&mut self, next: RunState) {
+        self.commands.push(SceneCommand::SetRunState(next));
+    }
+
+    pub(crate) fn into_commands(self) -> Vec<SceneCommand> {
+        self.commands
+    }
+}
+
+#[derive(Default)]
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+}
+
+impl SceneStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn top_name(&self) -> Option<&str> {
+        self.scenes.last().map(|scene| scene.name())
+    }
+
+    pub(crate) fn push(&mut self, scene: Box<dyn Scene>) {
+        self.scenes.push(scene);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Box<dyn Scene>> {
+        self.scenes.pop()
+    }
+
+    pub(crate) fn top_mut(&mut self) -> Option<&mut Box<dyn Scene>> {
+        self.scenes.last_mut()
+    }
+
+    // bottom to top, starting at the topmost scene that isn't an overlay
+    pub(crate) fn visible_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Scene>> {
+        let start = self
+            .scenes
+            .iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0);
+        self.scenes[start..].iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestScene {
+        name: &'static str,
+        is_overlay: bool,
+    }
+
+    impl Scene for TestScene {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn is_overlay(&self) -> bool {
+            self.is_overlay
+        }
+    }
+
+    fn stack(scenes: &[(&'static str, bool)]) -> SceneStack {
+        let mut stack = SceneStack::new();
+        for &(name, is_overlay) in scenes {
+            stack.push(Box::new(TestScene { name, is_overlay }));
+        }
+        stack
+    }
+
+    fn visible(stack: &mut SceneStack) -> Vec<String> {
+        stack
+            .visible_mut()
+            .map(|scene| scene.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn visible_starts_at_the_topmost_opaque_scene() {
+        let mut stack = stack(&[("title", false), ("level", false), ("pause", true)]);
+        assert_eq!(visible(&mut stack), ["level", "pause"]);
+
+        stack.push(Box::new(TestScene {
+            name: "options",
+            is_overlay: false,
+        }));
+        assert_eq!(visible(&mut stack), ["options"]);
+    }
+
+    #[test]
+    fn only_overlays_are_all_visible() {
+        let mut stack = stack(&[("hud", true), ("pause", true)]);
+        assert_eq!(visible(&mut stack), ["hud", "pause"]);
+        assert!(visible(&mut SceneStack::new()).is_empty());
+    }
+
+    #[test]
+    fn top_is_the_last_pushed() {
+        let mut stack = stack(&[("level", false), ("pause", true)]);
+        assert_eq!(stack.top_name(), Some("pause"));
+        assert_eq!(
+            stack.pop().map(|scene| scene.name().to_string()),
+            Some("pause".into())
+        );
+        assert_eq!(stack.top_name(), Some("level"));
+        assert_eq!(stack.len(), 1);
+    }
+}
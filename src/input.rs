@@ -0,0 +1,486 @@
+use anyhow::{anyhow, Result};
+use sdl2::{
+    controller::{Axis, Button},
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    mouse::{MouseButton, MouseWheelDirection},
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub mod gamepad;
+
+use gamepad::normalize_axis;
+
+// Actions the engine itself responds to, games add their own names alongside
+pub mod actions {
+    pub const STOP: &str = "stop";
+    pub const PAUSE: &str = "pause";
+    pub const TOGGLE_RUN: &str = "toggle_run";
+    pub const TOGGLE_DEBUG: &str = "toggle_debug";
+    pub const SCREENSHOT: &str = "screenshot";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputBinding {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Gamepad(Button),
+    GamepadAxis(Axis, AxisDirection), // pressed while pushed past AXIS_PRESS_THRESHOLD
+}
+
+// How far a stick or trigger must be pushed to count as pressing an action
+pub const AXIS_PRESS_THRESHOLD: f32 = 0.5;
+
+// Device id used for keyboard and mouse, controllers use their SDL instance id
+const KEYBOARD_MOUSE_DEVICE: u32 = u32::MAX;
+
+// Config file spelling of a binding:
+// "Escape", "Key:P"  keyboard, SDL key names
+// "Mouse:Left"       Left, Middle, Right, X1, X2
+// "Pad:a"            SDL game controller names, eg a, b, start, dpup, leftshoulder
+// "Pad:leftx-"       stick axis direction, triggers may omit the +, eg "Pad:righttrigger"
+pub fn parse_input_binding(s: &str) -> Result<InputBinding> {
+    let binding = match s.split_once(':') {
+        Some(("Mouse", name)) => match name {
+            "Left" => Some(MouseButton::Left),
+            "Middle" => Some(MouseButton::Middle),
+            "Right" => Some(MouseButton::Right),
+            "X1" => Some(MouseButton::X1),
+            "X2" => Some(MouseButton::X2),
+            _ => None,
+        }
+        .map(InputBinding::Mouse),
+        Some(("Pad", name)) => parse_gamepad_binding(name),
+        Some(("Key", name)) => Keycode::from_name(name).map(InputBinding::Key),
+        _ => Keycode::from_name(s).map(InputBinding::Key),
+    };
+    binding.ok_or_else(|| anyhow!("Unknown input binding: {s}"))
+}
+
+fn parse_gamepad_binding(name: &str) -> Option<InputBinding> {
+    if let Some(axis) = name.strip_suffix('+') {
+        return Axis::from_string(axis)
+            .map(|axis| InputBinding::GamepadAxis(axis, AxisDirection::Positive));
+    }
+    if let Some(axis) = name.strip_suffix('-') {
+        return Axis::from_string(axis)
+            .map(|axis| InputBinding::GamepadAxis(axis, AxisDirection::Negative));
+    }
+    Button::from_string(name)
+        .map(InputBinding::Gamepad)
+        .or_else(|| {
+            Axis::from_string(name)
+                .map(|axis| InputBinding::GamepadAxis(axis, AxisDirection::Positive))
+        })
+}
+
+pub type InputBindings = BTreeMap<String, Vec<InputBinding>>;
+
+pub fn default_bindings() -> InputBindings {
+    [
+        (actions::STOP, Keycode::Escape),
+        (actions::PAUSE, Keycode::P),
+        (actions::TOGGLE_RUN, Keycode::Semicolon),
+        (actions::TOGGLE_DEBUG, Keycode::D),
+        (actions::SCREENSHOT, Keycode::F12),
+    ]
+    .into_iter()
+    .map(|(action, key)| (action.to_string(), vec![InputBinding::Key(key)]))
+    .collect()
+}
+
+// Maps raw input to named actions and tracks their per frame state.
+// Each frame: handle_event for every polled event, then end_frame, then query.
+pub struct InputMap {
+    bindings: InputBindings,
+    held: HashMap<InputBinding, HashSet<u32>>, // devices holding each binding
+    pressed_this_frame: HashSet<InputBinding>, // catches a press and release within one frame
+    active: HashSet<String>,
+    prev_active: HashSet<String>,
+}
+
+impl InputMap {
+    pub fn new(bindings: InputBindings) -> Self {
+        Self {
+            bindings,
+            held: HashMap::new(),
+            pressed_this_frame: HashSet::new(),
+            active: HashSet::new(),
+            prev_active: HashSet::new(),
+        }
+    }
+
+    pub fn bind(&mut self, action: &str, binding: InputBinding) {
+        let bindings = self.bindings.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.bindings.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[InputBinding] {
+        self.bindings.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(key),
+                repeat: false,
+                ..
+            } => self.press(InputBinding::Key(key)),
+            Event::KeyUp {
+                keycode: Some(key), ..
+            } => self.release(InputBinding::Key(key)),
+            Event::MouseButtonDown { mouse_btn, .. } => self.press(InputBinding::Mouse(mouse_btn)),
+            Event::MouseButtonUp { mouse_btn, .. } => self.release(InputBinding::Mouse(mouse_btn)),
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.press_on(InputBinding::Gamepad(button), which)
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.release_on(InputBinding::Gamepad(button), which)
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let value = normalize_axis(value);
+                for (direction, is_pushed) in [
+                    (AxisDirection::Positive, value >= AXIS_PRESS_THRESHOLD),
+                    (AxisDirection::Negative, value <= -AXIS_PRESS_THRESHOLD),
+                ] {
+                    let binding = InputBinding::GamepadAxis(axis, direction);
+                    if is_pushed {
+                        self.press_on(binding, which);
+                    } else {
+                        self.release_on(binding, which);
+                    }
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                for devices in self.held.values_mut() {
+                    devices.remove(&which);
+                }
+                self.held.retain(|_, devices| !devices.is_empty());
+            }
+            // releases outside the window never arrive
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => self.held.clear(),
+            _ => {}
+        }
+    }
+
+    pub fn press(&mut self, binding: InputBinding) {
+        self.press_on(binding, KEYBOARD_MOUSE_DEVICE);
+    }
+
+    pub fn release(&mut self, binding: InputBinding) {
+        self.release_on(binding, KEYBOARD_MOUSE_DEVICE);
+    }
+
+    // a binding stays held until every device holding it lets go, eg two pads
+    fn press_on(&mut self, binding: InputBinding, device: u32) {
+        if self.held.entry(binding).or_default().insert(device) {
+            self.pressed_this_frame.insert(binding);
+        }
+    }
+
+    fn release_on(&mut self, binding: InputBinding, device: u32) {
+        if let Some(devices) = self.held.get_mut(&binding) {
+            devices.remove(&device);
+            if devices.is_empty() {
+                self.held.remove(&binding);
+            }
+        }
+    }
+
+    // Settles action state for this frame's queries
+    pub fn end_frame(&mut self) {
+        let active: HashSet<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bindings)| {
+                bindings.iter().any(|binding| {
+                    self.held.contains_key(binding) || self.pressed_this_frame.contains(binding)
+                })
+            })
+            .map(|(action, _)| action.clone())
+            .collect();
+        self.prev_active = std::mem::replace(&mut self.active, active);
+        self.pressed_this_frame.clear();
+    }
+
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.active.contains(action)
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.active.contains(action) && !self.prev_active.contains(action)
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        !self.active.contains(action) && self.prev_active.contains(action)
+    }
+}
+
+// Raw keyboard and mouse state for the current frame, kept as a Registry
+// resource so systems can read it during update.
+// Each frame: begin_frame, then handle_event for every polled event.
+#[derive(Default)]
+pub struct InputState {
+    held_keys: HashSet<Keycode>,
+    held_mouse_buttons: HashSet<MouseButton>,
+    mouse_position: (i32, i32),
+    mouse_delta: (i32, i32), // summed over the frame
+    wheel: (i32, i32),       // summed over the frame, +y is away from the user
+    text: String,            // typed this frame, only while text input is on
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // clears the per frame values, held state carries over
+    pub fn begin_frame(&mut self) {
+        self.mouse_delta = (0, 0);
+        self.wheel = (0, 0);
+        self.text.clear();
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown {
+                keycode: Some(key), ..
+            } => {
+                self.held_keys.insert(*key);
+            }
+            Event::KeyUp {
+                keycode: Some(key), ..
+            } => {
+                self.held_keys.remove(key);
+            }
+            Event::MouseButtonDown {
+                mouse_btn, x, y, ..
+            } => {
+                self.held_mouse_buttons.insert(*mouse_btn);
+                self.mouse_position = (*x, *y);
+            }
+            Event::MouseButtonUp {
+                mouse_btn, x, y, ..
+            } => {
+                self.held_mouse_buttons.remove(mouse_btn);
+                self.mouse_position = (*x, *y);
+            }
+            Event::MouseMotion {
+                x, y, xrel, yrel, ..
+            } => {
+                self.mouse_position = (*x, *y);
+                self.mouse_delta.0 += xrel;
+                self.mouse_delta.1 += yrel;
+            }
+            Event::MouseWheel {
+                x, y, direction, ..
+            } => {
+                let sign = match direction {
+                    MouseWheelDirection::Flipped => -1,
+                    _ => 1,
+                };
+                self.wheel.0 += x * sign;
+                self.wheel.1 += y * sign;
+            }
+            Event::TextInput { text, .. } => self.text.push_str(text),
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => {
+                self.held_keys.clear();
+                self.held_mouse_buttons.clear();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_key_held(&self, key: Keycode) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    pub fn held_keys(&self) -> impl Iterator<Item = &Keycode> {
+        self.held_keys.iter()
+    }
+
+    pub fn is_mouse_button_held(&self, button: MouseButton) -> bool {
+        self.held_mouse_buttons.contains(&button)
+    }
+
+    // window coordinates
+    pub fn mouse_position(&self) -> (i32, i32) {
+        self.mouse_position
+    }
+
+    pub fn mouse_delta(&self) -> (i32, i32) {
+        self.mouse_delta
+    }
+
+    pub fn wheel(&self) -> (i32, i32) {
+        self.wheel
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(which: u32, button: Button, is_down: bool) -> Event {
+        if is_down {
+            Event::ControllerButtonDown {
+                timestamp: 0,
+                which,
+                button,
+            }
+        } else {
+            Event::ControllerButtonUp {
+                timestamp: 0,
+                which,
+                button,
+            }
+        }
+    }
+
+    fn axis_motion(which: u32, axis: Axis, value: f32) -> Event {
+        Event::ControllerAxisMotion {
+            timestamp: 0,
+            which,
+            axis,
+            value: (value * i16::MAX as f32) as i16,
+        }
+    }
+
+    fn frame(input_map: &mut InputMap, events: &[Event]) {
+        for event in events {
+            input_map.handle_event(event);
+        }
+        input_map.end_frame();
+    }
+
+    fn map_with(action: &str, binding: InputBinding) -> InputMap {
+        let mut input_map = InputMap::new(InputBindings::new());
+        input_map.bind(action, binding);
+        input_map
+    }
+
+    #[test]
+    fn axis_binding_presses_past_the_threshold_and_releases_below_it() {
+        let mut input_map = map_with(
+            "right",
+            InputBinding::GamepadAxis(Axis::LeftX, AxisDirection::Positive),
+        );
+        input_map.bind(
+            "left",
+            InputBinding::GamepadAxis(Axis::LeftX, AxisDirection::Negative),
+        );
+
+        frame(&mut input_map, &[axis_motion(0, Axis::LeftX, 0.3)]);
+        assert!(!input_map.is_pressed("right"));
+
+        frame(&mut input_map, &[axis_motion(0, Axis::LeftX, 0.8)]);
+        assert!(input_map.just_pressed("right"));
+        assert!(!input_map.is_pressed("left"));
+
+        frame(&mut input_map, &[axis_motion(0, Axis::LeftX, 0.9)]);
+        assert!(input_map.is_pressed("right"));
+        assert!(!input_map.just_pressed("right"));
+
+        frame(&mut input_map, &[axis_motion(0, Axis::LeftX, -0.8)]);
+        assert!(input_map.just_released("right"));
+        assert!(input_map.just_pressed("left"));
+
+        frame(&mut input_map, &[axis_motion(0, Axis::LeftX, 0.0)]);
+        assert!(input_map.just_released("left"));
+    }
+
+    #[test]
+    fn two_pads_hold_one_binding_until_both_let_go() {
+        let mut input_map = map_with("fire", InputBinding::Gamepad(Button::A));
+        frame(
+            &mut input_map,
+            &[button(1, Button::A, true), button(2, Button::A, true)],
+        );
+        assert!(input_map.just_pressed("fire"));
+
+        frame(&mut input_map, &[button(1, Button::A, false)]);
+        assert!(input_map.is_pressed("fire"));
+        assert!(!input_map.just_released("fire"));
+
+        frame(&mut input_map, &[button(2, Button::A, false)]);
+        assert!(input_map.just_released("fire"));
+    }
+
+    #[test]
+    fn removing_a_pad_releases_only_what_it_held() {
+        let mut input_map = map_with("fire", InputBinding::Gamepad(Button::A));
+        frame(
+            &mut input_map,
+            &[button(1, Button::A, true), button(2, Button::A, true)],
+        );
+        frame(
+            &mut input_map,
+            &[Event::ControllerDeviceRemoved {
+                timestamp: 0,
+                which: 1,
+            }],
+        );
+        assert!(input_map.is_pressed("fire"));
+        frame(
+            &mut input_map,
+            &[Event::ControllerDeviceRemoved {
+                timestamp: 0,
+                which: 2,
+            }],
+        );
+        assert!(input_map.just_released("fire"));
+    }
+
+    #[test]
+    fn press_and_release_in_one_frame_still_counts() {
+        let mut input_map = map_with("fire", InputBinding::Gamepad(Button::A));
+        frame(
+            &mut input_map,
+            &[button(1, Button::A, true), button(1, Button::A, false)],
+        );
+        assert!(input_map.just_pressed("fire"));
+        frame(&mut input_map, &[]);
+        assert!(input_map.just_released("fire"));
+    }
+
+    #[test]
+    fn focus_loss_releases_everything() {
+        let mut input_map = map_with("click", InputBinding::Mouse(MouseButton::Left));
+        input_map.press(InputBinding::Mouse(MouseButton::Left));
+        input_map.end_frame();
+        assert!(input_map.is_pressed("click"));
+        frame(
+            &mut input_map,
+            &[Event::Window {
+                timestamp: 0,
+                window_id: 0,
+                win_event: WindowEvent::FocusLost,
+            }],
+        );
+        assert!(input_map.just_released("click"));
+    }
+}
@@ -0,0 +1,294 @@
+use sdl2::{
+    controller::{Axis, Button},
+    event::Event,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub const DEFAULT_DEADZONE: f32 = 0.15;
+
+// Normalizes a raw SDL axis value to -1.0..=1.0
+pub fn normalize_axis(value: i16) -> f32 {
+    (value as f32 / i16::MAX as f32).clamp(-1.0, 1.0)
+}
+
+// Values inside the deadzone read 0, the rest is rescaled so output still spans 0..=1
+pub fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude < deadzone {
+        0.0
+    } else {
+        value.signum() * ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+#[derive(Default)]
+struct PadState {
+    name: String,
+    buttons: HashSet<Button>,
+    axes: HashMap<Axis, i16>,
+}
+
+// Button and axis state per connected controller, keyed by SDL joystick instance id.
+// Kept as a Registry resource. Pads are added with connect, Game calls it after
+// opening the device, since ControllerDeviceAdded carries a device index and not
+// the instance id the other events use. After that synthetic events drive it the
+// same as real hardware.
+pub struct GamepadState {
+    pads: BTreeMap<u32, PadState>,
+    deadzone: f32,
+    connected_this_frame: Vec<u32>,
+    disconnected_this_frame: Vec<u32>,
+}
+
+impl GamepadState {
+    pub fn new(deadzone: f32) -> Self {
+        Self {
+            pads: BTreeMap::new(),
+            deadzone,
+            connected_this_frame: Vec::new(),
+            disconnected_this_frame: Vec::new(),
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.connected_this_frame.clear();
+        self.disconnected_this_frame.clear();
+    }
+
+    pub fn connect(&mut self, id: u32, name: &str) {
+        self.pads.insert(
+            id,
+            PadState {
+                name: name.to_string(),
+                ..PadState::default()
+            },
+        );
+        self.connected_this_frame.push(id);
+    }
+
+    pub fn disconnect(&mut self, id: u32) {
+        if self.pads.remove(&id).is_some() {
+            self.disconnected_this_frame.push(id);
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(pad) = self.pads.get_mut(&which) {
+                    pad.buttons.insert(button);
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(pad) = self.pads.get_mut(&which) {
+                    pad.buttons.remove(&button);
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                if let Some(pad) = self.pads.get_mut(&which) {
+                    pad.axes.insert(axis, value);
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which),
+            _ => {}
+        }
+    }
+
+    pub fn deadzone(&self) -> f32 {
+        self.deadzone
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone = deadzone.clamp(0.0, 0.99);
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pads.keys().copied()
+    }
+
+    pub fn is_connected(&self, id: u32) -> bool {
+        self.pads.contains_key(&id)
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.pads.get(&id).map(|pad| pad.name.as_str())
+    }
+
+    pub fn just_connected(&self) -> &[u32] {
+        &self.connected_this_frame
+    }
+
+    pub fn just_disconnected(&self) -> &[u32] {
+        &self.disconnected_this_frame
+    }
+
+    pub fn is_button_held(&self, id: u32, button: Button) -> bool {
+        self.pads
+            .get(&id)
+            .is_some_and(|pad| pad.buttons.contains(&button))
+    }
+
+    // on any connected pad, eg for single player
+    pub fn is_button_held_any(&self, button: Button) -> bool {
+        self.pads.values().any(|pad| pad.buttons.contains(&button))
+    }
+
+    // -1.0..=1.0 for sticks, 0.0..=1.0 for triggers, deadzone applied
+    pub fn axis(&self, id: u32, axis: Axis) -> f32 {
+        let raw = self.raw_axis(id, axis);
+        apply_deadzone(normalize_axis(raw), self.deadzone)
+    }
+
+    pub fn raw_axis(&self, id: u32, axis: Axis) -> i16 {
+        self.pads
+            .get(&id)
+            .and_then(|pad| pad.axes.get(&axis).copied())
+            .unwrap_or(0)
+    }
+
+    // Both stick axes with a radial deadzone, so diagonals aren't snapped to the axes
+    pub fn stick(&self, id: u32, stick: Stick) -> (f32, f32) {
+        let (x_axis, y_axis) = match stick {
+            Stick::Left => (Axis::LeftX, Axis::LeftY),
+            Stick::Right => (Axis::RightX, Axis::RightY),
+        };
+        let x = normalize_axis(self.raw_axis(id, x_axis));
+        let y = normalize_axis(self.raw_axis(id, y_axis));
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude == 0.0 {
+            return (0.0, 0.0);
+        }
+        let scaled = apply_deadzone(magnitude, self.deadzone);
+        (x / magnitude * scaled, y / magnitude * scaled)
+    }
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEADZONE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: u32 = 3;
+
+    fn axis_motion(which: u32, axis: Axis, value: f32) -> Event {
+        Event::ControllerAxisMotion {
+            timestamp: 0,
+            which,
+            axis,
+            value: (value * i16::MAX as f32) as i16,
+        }
+    }
+
+    fn connected() -> GamepadState {
+        let mut gamepads = GamepadState::default();
+        gamepads.connect(PAD, "pad");
+        gamepads
+    }
+
+    #[test]
+    fn deadzone_zeroes_small_values_and_rescales_the_rest() {
+        assert_eq!(apply_deadzone(0.1, 0.2), 0.0);
+        assert_eq!(apply_deadzone(-0.1, 0.2), 0.0);
+        assert!((apply_deadzone(0.6, 0.2) - 0.5).abs() < 1e-6);
+        assert!((apply_deadzone(-0.6, 0.2) + 0.5).abs() < 1e-6);
+        assert_eq!(apply_deadzone(1.0, 0.2), 1.0);
+        assert_eq!(normalize_axis(i16::MIN), -1.0);
+    }
+
+    #[test]
+    fn axis_reads_through_the_deadzone() {
+        let mut gamepads = connected();
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftX, 0.1));
+        assert_eq!(gamepads.axis(PAD, Axis::LeftX), 0.0);
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftX, 1.0));
+        assert_eq!(gamepads.axis(PAD, Axis::LeftX), 1.0);
+        assert_eq!(gamepads.axis(PAD, Axis::LeftY), 0.0);
+    }
+
+    #[test]
+    fn stick_deadzone_is_radial() {
+        let mut gamepads = connected();
+        // each axis alone is inside the deadzone, together they are outside it
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftX, 0.12));
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftY, 0.12));
+        assert_eq!(gamepads.axis(PAD, Axis::LeftX), 0.0);
+        let (x, y) = gamepads.stick(PAD, Stick::Left);
+        assert!(x > 0.0);
+        assert!((x - y).abs() < 1e-6);
+
+        // a full diagonal keeps its direction and is clamped to length 1
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftX, 1.0));
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftY, -1.0));
+        let (x, y) = gamepads.stick(PAD, Stick::Left);
+        assert!(((x * x + y * y).sqrt() - 1.0).abs() < 1e-6);
+        assert!((x + y).abs() < 1e-6);
+        assert_eq!(gamepads.stick(PAD, Stick::Right), (0.0, 0.0));
+    }
+
+    #[test]
+    fn buttons_are_tracked_per_pad() {
+        let mut gamepads = connected();
+        gamepads.connect(PAD + 1, "other");
+        gamepads.handle_event(&Event::ControllerButtonDown {
+            timestamp: 0,
+            which: PAD,
+            button: Button::A,
+        });
+        assert!(gamepads.is_button_held(PAD, Button::A));
+        assert!(!gamepads.is_button_held(PAD + 1, Button::A));
+        assert!(gamepads.is_button_held_any(Button::A));
+
+        gamepads.handle_event(&Event::ControllerButtonUp {
+            timestamp: 0,
+            which: PAD,
+            button: Button::A,
+        });
+        assert!(!gamepads.is_button_held_any(Button::A));
+    }
+
+    #[test]
+    fn events_for_unknown_pads_are_ignored() {
+        let mut gamepads = GamepadState::default();
+        gamepads.handle_event(&Event::ControllerButtonDown {
+            timestamp: 0,
+            which: PAD,
+            button: Button::A,
+        });
+        gamepads.handle_event(&axis_motion(PAD, Axis::LeftX, 1.0));
+        assert!(!gamepads.is_connected(PAD));
+        assert!(!gamepads.is_button_held_any(Button::A));
+        assert_eq!(gamepads.raw_axis(PAD, Axis::LeftX), 0);
+    }
+
+    #[test]
+    fn connect_and_remove_are_reported_for_one_frame() {
+        let mut gamepads = connected();
+        assert_eq!(gamepads.just_connected(), [PAD]);
+        assert_eq!(gamepads.name(PAD), Some("pad"));
+
+        gamepads.begin_frame();
+        gamepads.handle_event(&Event::ControllerDeviceRemoved {
+            timestamp: 0,
+            which: PAD,
+        });
+        assert!(gamepads.just_connected().is_empty());
+        assert_eq!(gamepads.just_disconnected(), [PAD]);
+        assert!(!gamepads.is_connected(PAD));
+
+        gamepads.begin_frame();
+        assert!(gamepads.just_disconnected().is_empty());
+    }
+}
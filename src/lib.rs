@@ -1,4 +1,12 @@
+pub mod asset_store;
+pub mod audio;
+pub mod camera;
+pub mod cli;
+pub mod config;
 pub mod dsa;
 pub mod ecs;
+pub mod event_bus;
 pub mod game;
+pub mod input;
 pub mod logger;
+pub mod text;
@@ -1,13 +1,24 @@
+use anyhow::{anyhow, Context};
 use lazy_static::lazy_static;
-use std::sync::Mutex;
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Mutex, Once, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 // extern crate chrono;
 
 use chrono::prelude::*;
-// use std::fs::OpenOptions;
-// use std::io::prelude::*;
 
-#[derive(Clone, Copy)]
+use crate::dsa::FixedSizeQueue;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum LogLevel {
     Debug = 0,
     Info = 1,
@@ -16,52 +27,506 @@ pub enum LogLevel {
     Critical = 4,
 }
 
+const ANSI_RESET: &str = "\x1b[0m";
+
+impl LogLevel {
+    fn ansi_color(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "\x1b[36m",      // cyan
+            LogLevel::Info => "\x1b[32m",       // green
+            LogLevel::Warning => "\x1b[33m",    // yellow
+            LogLevel::Error => "\x1b[31m",      // red
+            LogLevel::Critical => "\x1b[1;41m", // bold on red background
+        }
+    }
+
+    // Error and Critical go to stderr so they survive stdout redirection
+    fn is_stderr(self) -> bool {
+        matches!(self, LogLevel::Error | LogLevel::Critical)
+    }
+}
+
+// Env var layer for the log filter, same syntax as `-l`
+pub const LOG_FILTER_ENV_VAR: &str = "KENGEN_LOG";
+
+// Default level plus per-module-prefix overrides, eg: "info,kengen::ecs=debug"
+// The longest matching module prefix wins, otherwise the default applies
+#[derive(Clone, Debug)]
+pub struct LogFilter {
+    default: LogLevel,
+    directives: Vec<(String, LogLevel)>,
+}
+
+impl LogFilter {
+    pub fn new(default: LogLevel) -> Self {
+        Self {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    pub fn add_directive(&mut self, module_prefix: &str, level: LogLevel) {
+        self.directives
+            .retain(|(prefix, _)| prefix != module_prefix);
+        self.directives.push((module_prefix.to_string(), level));
+        // longest prefix first so the first match is the most specific
+        self.directives
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    }
+
+    pub fn default_level(&self) -> LogLevel {
+        self.default
+    }
+
+    // lowest level any module can log at, used to reject messages without locking
+    pub fn min_level(&self) -> LogLevel {
+        self.directives
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, |min, level| {
+                if (level as u8) < (min as u8) {
+                    level
+                } else {
+                    min
+                }
+            })
+    }
+
+    pub fn has_directives(&self) -> bool {
+        !self.directives.is_empty()
+    }
+
+    pub fn level_for(&self, module_path: &str) -> LogLevel {
+        self.directives
+            .iter()
+            .find(|(prefix, _)| is_module_prefix(prefix, module_path))
+            .map_or(self.default, |(_, level)| *level)
+    }
+}
+
+impl From<LogLevel> for LogFilter {
+    fn from(level: LogLevel) -> Self {
+        Self::new(level)
+    }
+}
+
+// "kengen::ecs" matches "kengen::ecs" and "kengen::ecs::systems" but not "kengen::ecsx"
+fn is_module_prefix(prefix: &str, module_path: &str) -> bool {
+    match module_path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+pub fn parse_log_level_value(s: &str) -> Result<LogLevel, anyhow::Error> {
+    match s {
+        "debug" => Ok(LogLevel::Debug),
+        "info" => Ok(LogLevel::Info),
+        "warning" => Ok(LogLevel::Warning),
+        "error" => Ok(LogLevel::Error),
+        "critical" => Ok(LogLevel::Critical),
+        x => Err(anyhow!("Malformed or empty log level value: {x}")),
+    }
+}
+
+// Comma separated list of `level` and `module::path=level` directives
+// eg: "info,kengen::ecs=debug,kengen::game=warning"
+pub fn parse_log_filter(s: &str) -> Result<LogFilter, anyhow::Error> {
+    let mut filter = LogFilter::new(LogLevel::Debug);
+    for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match directive.split_once('=') {
+            Some((module_prefix, level)) => {
+                let module_prefix = module_prefix.trim();
+                if module_prefix.is_empty() {
+                    return Err(anyhow!("Missing module path in log directive: {directive}"));
+                }
+                filter.add_directive(module_prefix, parse_log_level_value(level.trim())?);
+            }
+            None => filter.default = parse_log_level_value(directive)?,
+        }
+    }
+    Ok(filter)
+}
+
+// What the async backend does when its channel is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    Drop,  // discard the line and count it, never stalls the frame
+    Block, // wait for the logging thread to catch up, never loses a line
+}
+
+pub fn parse_overflow_policy_value(s: &str) -> Result<OverflowPolicy, anyhow::Error> {
+    match s {
+        "drop" => Ok(OverflowPolicy::Drop),
+        "block" => Ok(OverflowPolicy::Block),
+        x => Err(anyhow!("Malformed or empty log overflow policy value: {x}")),
+    }
+}
+
+pub const DEFAULT_ASYNC_LOG_CAPACITY: usize = 1024;
+const LOGGER_THREAD_NAME: &str = "kengen-logger";
+// upper bound on waiting for the logging thread, eg when flushing from a panic
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Where lines are written, the in-memory history gets every line either way
 enum LoggerTarget {
-    File(String),
+    File(File), // appended to, without colors
     Console,
 }
 
-pub struct Logger {
-    target: LoggerTarget,
+pub struct Logger;
+
+pub const DEFAULT_LOG_HISTORY_SIZE: usize = 256;
+
+// A line kept in the in-memory history, eg for the in-game debug overlay
+#[derive(Clone)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub line: String,
 }
 
+// Where a log macro was invoked, filled in by klog! and friends
+#[derive(Clone, Copy)]
+pub struct CallSite {
+    pub module_path: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+// Everything needed to write a line, captured at the call so the time is accurate
+// even when the write happens later on the logging thread
+struct PendingLine {
+    level: LogLevel,
+    time: DateTime<Local>,
+    site: Option<CallSite>,
+    msg: String,
+}
+
+enum BackendMessage {
+    Line(PendingLine),
+    Flush(SyncSender<()>),
+}
+
+struct AsyncBackend {
+    sender: SyncSender<BackendMessage>,
+    worker: JoinHandle<()>,
+    policy: OverflowPolicy,
+}
+
+// Level checks read these atomics so filtered out messages never take a lock,
+// the filter itself is only locked when per-module directives are set
+static DEFAULT_LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Debug as u8);
+static MIN_LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Debug as u8);
+static HAS_LOG_DIRECTIVES: AtomicBool = AtomicBool::new(false);
+static ASYNC_ENABLED: AtomicBool = AtomicBool::new(false);
+static DROPPED_LOG_LINES: AtomicU64 = AtomicU64::new(0);
+static PANIC_HOOK: Once = Once::new();
+
 lazy_static! {
-    static ref GLOBAL_LOG_LEVEL: Mutex<LogLevel> = Mutex::new(LogLevel::Debug);
+    static ref ASYNC_BACKEND: RwLock<Option<AsyncBackend>> = RwLock::new(None);
+    static ref GLOBAL_LOG_TARGET: Mutex<LoggerTarget> = Mutex::new(LoggerTarget::Console);
+    static ref GLOBAL_LOG_FILTER: Mutex<LogFilter> = Mutex::new(LogFilter::new(LogLevel::Debug));
+    // last N emitted lines, oldest dropped first
+    static ref GLOBAL_LOG_HISTORY: Mutex<FixedSizeQueue<LogRecord>> =
+        Mutex::new(FixedSizeQueue::new(DEFAULT_LOG_HISTORY_SIZE));
+    // Color is decided once per stream: off when NO_COLOR is set to a non empty value
+    // (see no-color.org) or when the stream isn't a terminal, eg piped into a file
+    static ref STDOUT_COLOR: bool =
+        !is_no_color(env::var_os("NO_COLOR").as_deref()) && io::stdout().is_terminal();
+    static ref STDERR_COLOR: bool =
+        !is_no_color(env::var_os("NO_COLOR").as_deref()) && io::stderr().is_terminal();
+}
+
+// NO_COLOR= (empty) leaves color on
+fn is_no_color(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|value| !value.is_empty())
 }
 
 impl Logger {
-    pub fn new(level: LogLevel, target: Option<&str>) -> Self {
+    // target is a file path to append to, None writes to stdout and stderr
+    pub fn new(filter: impl Into<LogFilter>, target: Option<&str>) -> Result<Self, anyhow::Error> {
         // In this pattern, you're effectively using the Logger::new as a way to
         // optionally configure the logging behavior. If it's never called, the
         // system simply proceeds with defaults.
         // see lazy_static!
 
-        *GLOBAL_LOG_LEVEL.lock().unwrap() = level;
+        Self::set_filter(filter.into());
         let target = match target {
-            Some(path) => LoggerTarget::File(path.to_string()),
+            Some(path) => LoggerTarget::File(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open log output {path}"))?,
+            ),
             None => LoggerTarget::Console,
         };
-        Self { target }
+        *GLOBAL_LOG_TARGET
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = target;
+        Ok(Self)
+    }
+
+    pub fn set_filter(filter: LogFilter) {
+        let mut global_filter = GLOBAL_LOG_FILTER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        DEFAULT_LOG_LEVEL.store(filter.default_level() as u8, Ordering::Relaxed);
+        MIN_LOG_LEVEL.store(filter.min_level() as u8, Ordering::Relaxed);
+        HAS_LOG_DIRECTIVES.store(filter.has_directives(), Ordering::Relaxed);
+        *global_filter = filter;
+    }
+
+    pub fn enabled(level: LogLevel) -> bool {
+        DEFAULT_LOG_LEVEL.load(Ordering::Relaxed) <= level as u8
+    }
+
+    pub fn enabled_for(level: LogLevel, module_path: &str) -> bool {
+        if (level as u8) < MIN_LOG_LEVEL.load(Ordering::Relaxed) {
+            return false;
+        }
+        if !HAS_LOG_DIRECTIVES.load(Ordering::Relaxed) {
+            return Self::enabled(level);
+        }
+        GLOBAL_LOG_FILTER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .level_for(module_path) as u8
+            <= level as u8
+    }
+
+    // Moves writing off the calling thread onto a background logging thread fed
+    // by a channel holding up to `capacity` lines. Also installs a panic hook that
+    // flushes pending lines before the panic message is printed.
+    pub fn start_async(capacity: usize, policy: OverflowPolicy) -> Result<(), anyhow::Error> {
+        let mut backend = ASYNC_BACKEND
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if backend.is_some() {
+            return Err(anyhow!("Async logging already started"));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<BackendMessage>(capacity);
+        let worker = thread::Builder::new()
+            .name(LOGGER_THREAD_NAME.to_string())
+            .spawn(move || {
+                for message in receiver {
+                    match message {
+                        BackendMessage::Line(pending) => Self::write_line(pending),
+                        BackendMessage::Flush(ack) => {
+                            Self::flush_stdio();
+                            let _ = ack.send(());
+                        }
+                    }
+                }
+                Self::flush_stdio();
+            })?;
+
+        *backend = Some(AsyncBackend {
+            sender,
+            worker,
+            policy,
+        });
+        ASYNC_ENABLED.store(true, Ordering::Release);
+
+        PANIC_HOOK.call_once(|| {
+            let prev_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                Self::flush();
+                prev_hook(info);
+            }));
+        });
+        Ok(())
+    }
+
+    // Blocks until every line logged before this call has been written
+    pub fn flush() {
+        let is_logger_thread = thread::current().name() == Some(LOGGER_THREAD_NAME);
+        if ASYNC_ENABLED.load(Ordering::Acquire) && !is_logger_thread {
+            let backend = ASYNC_BACKEND.read().unwrap_or_else(PoisonError::into_inner);
+            if let Some(backend) = backend.as_ref() {
+                let (ack_sender, ack_receiver) = mpsc::sync_channel(1);
+                if backend
+                    .sender
+                    .send(BackendMessage::Flush(ack_sender))
+                    .is_ok()
+                {
+                    let _ = ack_receiver.recv_timeout(FLUSH_TIMEOUT);
+                }
+            }
+        }
+        Self::flush_stdio();
+    }
+
+    // Drains the channel, stops the logging thread and returns to synchronous writes
+    pub fn stop_async() {
+        ASYNC_ENABLED.store(false, Ordering::Release);
+        let backend = ASYNC_BACKEND
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(AsyncBackend { sender, worker, .. }) = backend {
+            drop(sender);
+            let _ = worker.join();
+        }
+    }
+
+    // Lines discarded by OverflowPolicy::Drop since startup
+    pub fn dropped_count() -> u64 {
+        DROPPED_LOG_LINES.load(Ordering::Relaxed)
     }
 
     fn log(level: LogLevel, msg: &str) {
-        let global_level = GLOBAL_LOG_LEVEL.lock().unwrap();
+        if Self::enabled(level) {
+            Self::emit(level, None, msg.to_string());
+        }
+    }
 
-        let formatted_msg = Self::format_msg(level, &get_time_date_string(), msg);
+    // Entry point for the logging macros, which have already checked `enabled_for`
+    // so the message is only formatted when it will actually be written
+    #[doc(hidden)]
+    pub fn log_at(level: LogLevel, site: &CallSite, args: fmt::Arguments) {
+        Self::emit(level, Some(site), fmt::format(args));
+    }
 
-        if *global_level as u8 <= level as u8 {
-            println!("{}", formatted_msg);
+    pub fn set_history_size(size: usize) {
+        GLOBAL_LOG_HISTORY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_limit(size);
+    }
+
+    // Up to `max` of the most recent lines at or above `min_level`, oldest first
+    pub fn recent(min_level: LogLevel, max: usize) -> Vec<LogRecord> {
+        let history = GLOBAL_LOG_HISTORY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut records: Vec<LogRecord> = history
+            .iter()
+            .rev()
+            .filter(|record| record.level as u8 >= min_level as u8)
+            .take(max)
+            .cloned()
+            .collect();
+        records.reverse();
+        records
+    }
 
-            // match level {
-            //     LogLevel::Debug => println!("{}", formatted_msg),
-            //     LogLevel::Info => println!("{}", formatted_msg),
-            //     LogLevel::Warning => println!("{}", formatted_msg),
-            //     LogLevel::Error => println!("{}}", formatted_msg),
-            //     LogLevel::Critical => println!("{}", formatted_msg),
-            // }
+    pub fn clear_history() {
+        GLOBAL_LOG_HISTORY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    fn emit(level: LogLevel, site: Option<&CallSite>, msg: String) {
+        let pending = PendingLine {
+            level,
+            time: Local::now(),
+            site: site.copied(),
+            msg,
+        };
+        let unsent = if ASYNC_ENABLED.load(Ordering::Acquire) {
+            Self::send_async(pending)
+        } else {
+            Some(pending)
+        };
+        if let Some(pending) = unsent {
+            Self::write_line(pending);
         }
     }
 
+    // Hands the line to the logging thread, gives it back if there is no thread to take it
+    fn send_async(pending: PendingLine) -> Option<PendingLine> {
+        let backend = ASYNC_BACKEND.read().unwrap_or_else(PoisonError::into_inner);
+        let Some(backend) = backend.as_ref() else {
+            return Some(pending);
+        };
+        let message = BackendMessage::Line(pending);
+        let unsent = match backend.policy {
+            OverflowPolicy::Block => backend.sender.send(message).err().map(|e| e.0),
+            OverflowPolicy::Drop => match backend.sender.try_send(message) {
+                Ok(()) => None,
+                Err(TrySendError::Full(_)) => {
+                    DROPPED_LOG_LINES.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+                Err(TrySendError::Disconnected(message)) => Some(message),
+            },
+        };
+        match unsent {
+            Some(BackendMessage::Line(pending)) => Some(pending),
+            _ => None,
+        }
+    }
+
+    fn flush_stdio() {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        // try_lock, this runs from the panic hook, which may fire while this
+        // thread is inside write_line
+        if let Ok(mut target) = GLOBAL_LOG_TARGET.try_lock() {
+            if let LoggerTarget::File(file) = &mut *target {
+                let _ = file.flush();
+            }
+        }
+    }
+
+    fn write_line(pending: PendingLine) {
+        let PendingLine {
+            level,
+            time,
+            site,
+            msg,
+        } = pending;
+        let site = site.as_ref();
+        let msg = msg.as_str();
+        let time = format_time_date(&time);
+        let mut target = GLOBAL_LOG_TARGET
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let line = match &mut *target {
+            LoggerTarget::File(file) => {
+                let line = Self::format_msg(level, &time, site, msg, false);
+                if let Err(e) = writeln!(file, "{}", line) {
+                    let mut stderr = io::stderr().lock();
+                    let _ = writeln!(stderr, "Failed to write log output: {e}");
+                    let _ = writeln!(stderr, "{}", line);
+                }
+                line
+            }
+            LoggerTarget::Console => {
+                let is_stderr = level.is_stderr();
+                let use_color = if is_stderr {
+                    *STDERR_COLOR
+                } else {
+                    *STDOUT_COLOR
+                };
+                let formatted_msg = Self::format_msg(level, &time, site, msg, use_color);
+
+                // Write errors are ignored, println! would panic on a closed
+                // pipe, eg `kengen | head`, with the target lock held
+                if is_stderr {
+                    let _ = writeln!(io::stderr().lock(), "{}", formatted_msg);
+                } else {
+                    let _ = writeln!(io::stdout().lock(), "{}", formatted_msg);
+                }
+
+                if use_color {
+                    Self::format_msg(level, &time, site, msg, false)
+                } else {
+                    formatted_msg
+                }
+            }
+        };
+        drop(target);
+        GLOBAL_LOG_HISTORY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(LogRecord { level, line });
+    }
+
     pub fn dbg(msg: &str) {
         Self::log(LogLevel::Debug, msg);
     }
@@ -82,7 +547,13 @@ impl Logger {
         Self::log(LogLevel::Critical, msg);
     }
 
-    fn format_msg(level: LogLevel, time: &str, msg: &str) -> String {
+    fn format_msg(
+        level: LogLevel,
+        time: &str,
+        site: Option<&CallSite>,
+        msg: &str,
+        use_color: bool,
+    ) -> String {
         let level_str = match level {
             LogLevel::Debug => "DBG",
             LogLevel::Info => "INF",
@@ -90,11 +561,149 @@ impl Logger {
             LogLevel::Error => "ERR",
             LogLevel::Critical => "CRT",
         };
-        format!("{} [{}] {}", level_str, time, msg)
+        let level_str = if use_color {
+            format!("{}{}{}", level.ansi_color(), level_str, ANSI_RESET)
+        } else {
+            level_str.to_string()
+        };
+        match site {
+            Some(site) => format!(
+                "{} [{}] {} ({}:{}) {}",
+                level_str, time, site.module_path, site.file, site.line, msg
+            ),
+            None => format!("{} [{}] {}", level_str, time, msg),
+        }
     }
 }
 
 pub fn get_time_date_string() -> String {
-    let now = Local::now();
-    now.format("%y/%m/%d %H:%M:%S").to_string()
+    format_time_date(&Local::now())
+}
+
+fn format_time_date(time: &DateTime<Local>) -> String {
+    time.format("%y/%m/%d %H:%M:%S").to_string()
+}
+
+// Logging macros: take format! style args, skip formatting entirely when the
+// level is filtered out, and tag the line with the caller's module/file/line
+// eg: kinfo!("Spawned {} entities", n);
+#[macro_export]
+macro_rules! klog {
+    ($level:expr, $($arg:tt)+) => {{
+        let level = $level;
+        if $crate::logger::Logger::enabled_for(level, module_path!()) {
+            $crate::logger::Logger::log_at(
+                level,
+                &$crate::logger::CallSite {
+                    module_path: module_path!(),
+                    file: file!(),
+                    line: line!(),
+                },
+                format_args!($($arg)+),
+            );
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! kdbg {
+    ($($arg:tt)+) => { $crate::klog!($crate::logger::LogLevel::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! kinfo {
+    ($($arg:tt)+) => { $crate::klog!($crate::logger::LogLevel::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! kwarn {
+    ($($arg:tt)+) => { $crate::klog!($crate::logger::LogLevel::Warning, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! kerr {
+    ($($arg:tt)+) => { $crate::klog!($crate::logger::LogLevel::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! kcrit {
+    ($($arg:tt)+) => { $crate::klog!($crate::logger::LogLevel::Critical, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_color_needs_a_non_empty_value() {
+        assert!(!is_no_color(None));
+        assert!(!is_no_color(Some(OsStr::new(""))));
+        assert!(is_no_color(Some(OsStr::new("1"))));
+        assert!(is_no_color(Some(OsStr::new("false"))));
+    }
+
+    #[test]
+    fn parse_filter_reads_default_and_directives() {
+        let filter = parse_log_filter("info, kengen::ecs = debug ,kengen::game=error").unwrap();
+        assert_eq!(filter.default_level(), LogLevel::Info);
+        assert_eq!(filter.min_level(), LogLevel::Debug);
+        assert!(filter.has_directives());
+        assert_eq!(filter.level_for("kengen::ecs"), LogLevel::Debug);
+        assert_eq!(filter.level_for("kengen::game"), LogLevel::Error);
+        assert_eq!(filter.level_for("kengen::audio"), LogLevel::Info);
+    }
+
+    #[test]
+    fn parse_filter_without_a_level_defaults_to_debug() {
+        let filter = parse_log_filter("").unwrap();
+        assert_eq!(filter.default_level(), LogLevel::Debug);
+        assert!(!filter.has_directives());
+        let filter = parse_log_filter("kengen::ecs=warning").unwrap();
+        assert_eq!(filter.default_level(), LogLevel::Debug);
+        assert_eq!(filter.level_for("kengen::ecs"), LogLevel::Warning);
+    }
+
+    #[test]
+    fn parse_filter_rejects_bad_directives() {
+        assert!(parse_log_filter("loud").is_err());
+        assert!(parse_log_filter("=debug").is_err());
+        assert!(parse_log_filter("kengen::ecs=").is_err());
+        assert!(parse_log_filter("info,kengen::ecs=verbose").is_err());
+    }
+
+    #[test]
+    fn longest_prefix_wins_regardless_of_order() {
+        let mut filter = LogFilter::new(LogLevel::Warning);
+        filter.add_directive("kengen::ecs::systems", LogLevel::Error);
+        filter.add_directive("kengen", LogLevel::Info);
+        filter.add_directive("kengen::ecs", LogLevel::Debug);
+
+        assert_eq!(filter.level_for("kengen::ecs::systems"), LogLevel::Error);
+        assert_eq!(
+            filter.level_for("kengen::ecs::systems::render"),
+            LogLevel::Error
+        );
+        assert_eq!(filter.level_for("kengen::ecs::components"), LogLevel::Debug);
+        assert_eq!(filter.level_for("kengen::game"), LogLevel::Info);
+        assert_eq!(filter.level_for("other_crate"), LogLevel::Warning);
+    }
+
+    #[test]
+    fn later_directive_for_the_same_prefix_replaces_the_earlier() {
+        let filter = parse_log_filter("kengen::ecs=debug,kengen::ecs=error").unwrap();
+        assert_eq!(filter.level_for("kengen::ecs"), LogLevel::Error);
+        assert_eq!(filter.min_level(), LogLevel::Debug); // the default
+    }
+
+    #[test]
+    fn module_prefix_stops_at_path_boundaries() {
+        assert!(is_module_prefix("kengen::ecs", "kengen::ecs"));
+        assert!(is_module_prefix("kengen::ecs", "kengen::ecs::systems"));
+        assert!(!is_module_prefix("kengen::ecs", "kengen::ecsx"));
+        assert!(!is_module_prefix("kengen::ecs", "kengen"));
+        assert!(!is_module_prefix("kengen::ecs::systems", "kengen::ecs"));
+
+        let filter = parse_log_filter("error,kengen::ecs=debug").unwrap();
+        assert_eq!(filter.level_for("kengen::ecsx"), LogLevel::Error);
+    }
 }
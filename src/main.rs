@@ -1,13 +1,42 @@
-use kengen::ecs::components::HealthComponent;
-#[allow(warnings, dead_code)]
+use kengen::cli::{self, CliCommand};
+use kengen::config::GameConfiguration;
 use kengen::game::Game;
 
 fn main() {
-    // let mut game = Game::new().unwrap_or_else(|e| {
-    //     println!("{e}");
-    //     std::process::exit(1);
-    // });
+    let overrides = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(CliCommand::Run(overrides)) => overrides,
+        Ok(CliCommand::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(CliCommand::Version) => {
+            println!("{}", cli::version());
+            return;
+        }
+        Err(e) => {
+            eprintln!("{e:#}\n\n{}", cli::USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    // game.run();
-    // game.destroy();
+    let config = GameConfiguration::load(overrides).unwrap_or_else(|e| {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    });
+
+    let mut game = Game::with_config(config).unwrap_or_else(|e| {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    });
+
+    if game.is_headless() {
+        let max_frames = game.config().headless_frames.unwrap_or(u64::MAX);
+        let result = game.run_headless(max_frames);
+        game.destroy();
+        drop(game); // exit skips destructors, flush logs first
+        std::process::exit(result.exit_code());
+    }
+
+    game.run();
+    game.destroy();
 }
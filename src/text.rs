@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Context, Result};
+use nalgebra::Vector2;
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{Canvas, Texture, TextureCreator},
+    ttf::{self, Font, Sdl2TtfContext},
+    video::{Window, WindowContext},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::ecs::components::{TextAlign, TextLabelComponent};
+
+// Frames a rendered line stays cached without being drawn, eg an FPS counter
+// makes a new line most frames
+const TEXT_CACHE_FRAMES: u64 = 120;
+
+// Fonts borrow the ttf context, so it lives for the whole program
+static TTF_CONTEXT: OnceLock<Sdl2TtfContext> = OnceLock::new();
+
+fn ttf_context() -> Result<&'static Sdl2TtfContext> {
+    if let Some(context) = TTF_CONTEXT.get() {
+        return Ok(context);
+    }
+    let context = ttf::init()
+        .map_err(|e| anyhow!("{}", e))
+        .with_context(|| "Failed to init SDL_ttf".to_owned())?;
+    Ok(TTF_CONTEXT.get_or_init(|| context))
+}
+
+// Splits text into the lines it is drawn as. "\n" always breaks, with a wrap
+// width lines break at spaces and words wider than the width break anywhere.
+// measure gives the drawn width of a string.
+pub fn wrap_text(
+    text: &str,
+    wrap_width: Option<u32>,
+    measure: impl Fn(&str) -> u32,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let Some(wrap_width) = wrap_width else {
+            lines.push(paragraph.to_string());
+            continue;
+        };
+        let mut line = String::new();
+        for word in paragraph.split(' ').filter(|word| !word.is_empty()) {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{line} {word}")
+            };
+            if measure(&candidate) <= wrap_width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for ch in word.chars() {
+                line.push(ch);
+                if measure(&line) > wrap_width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, ch.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// x of a line within its block
+pub fn align_offset(align: TextAlign, line_width: u32, block_width: u32) -> i32 {
+    let free = block_width.saturating_sub(line_width) as i32;
+    match align {
+        TextAlign::Left => 0,
+        TextAlign::Center => free / 2,
+        TextAlign::Right => free,
+    }
+}
+
+// x of the block's left edge for a label anchored at anchor_x
+pub fn block_left(align: TextAlign, anchor_x: i32, block_width: u32) -> i32 {
+    match align {
+        TextAlign::Left => anchor_x,
+        TextAlign::Center => anchor_x - block_width as i32 / 2,
+        TextAlign::Right => anchor_x - block_width as i32,
+    }
+}
+
+struct CachedLine {
+    texture: Texture, // white, tinted with color mod when drawn
+    width: u32,
+    height: u32,
+    last_used: u64, // frame
+}
+
+// (font id, size, line)
+type LineKey = (String, u16, String);
+
+// Draws text to the window with fonts cached per (font, size) and rendered lines
+// cached per (font, size, string). Font paths are relative to the assets dir.
+pub struct TextRenderer {
+    ttf: &'static Sdl2TtfContext,
+    texture_creator: TextureCreator<WindowContext>,
+    root: PathBuf,
+    font_paths: HashMap<String, PathBuf>,
+    fonts: HashMap<(String, u16), Font<'static, 'static>>,
+    lines: HashMap<LineKey, CachedLine>,
+    frame: u64,
+}
+
+impl TextRenderer {
+    pub fn new(
+        texture_creator: TextureCreator<WindowContext>,
+        root: impl Into<PathBuf>,
+    ) -> Result<Self> {
+        Ok(Self {
+            ttf: ttf_context()?,
+            texture_creator,
+            root: root.into(),
+            font_paths: HashMap::new(),
+            fonts: HashMap::new(),
+            lines: HashMap::new(),
+            frame: 0,
+        })
+    }
+
+    // Loaded per size on first use, opened once here so a bad path fails early.
+    // Replaces any font with the same id.
+    pub fn add_font(&mut self, id: &str, path: &Path) -> Result<()> {
+        let path = self.root.join(path);
+        self.ttf
+            .load_font(&path, 12)
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Failed to load font {}", path.display()))?;
+        self.fonts.retain(|(font_id, _), _| font_id != id);
+        self.remove_lines(|(font_id, _, _), _| font_id == id);
+        self.font_paths.insert(id.to_string(), path);
+        Ok(())
+    }
+
+    pub fn has_font(&self, id: &str) -> bool {
+        self.font_paths.contains_key(id)
+    }
+
+    fn load_font(&mut self, id: &str, size: u16) -> Result<&Font<'static, 'static>> {
+        let key = (id.to_string(), size);
+        if !self.fonts.contains_key(&key) {
+            let path = self
+                .font_paths
+                .get(id)
+                .ok_or_else(|| anyhow!("Unknown font {id}"))?;
+            let font = self
+                .ttf
+                .load_font(path, size)
+                .map_err(|e| anyhow!("{}", e))
+                .with_context(|| format!("Failed to load font {} at {size}pt", path.display()))?;
+            self.fonts.insert(key.clone(), font);
+        }
+        Ok(&self.fonts[&key])
+    }
+
+    // (width, height) in pixels of text on one line
+    pub fn measure(&mut self, font_id: &str, size: u16, text: &str) -> Result<(u32, u32)> {
+        self.load_font(font_id, size)?
+            .size_of(text)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    // Draws the label with its anchor at screen_position, see TextLabelComponent
+    pub fn draw_label(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        label: &TextLabelComponent,
+        screen_position: Vector2<f32>,
+    ) -> Result<()> {
+        self.draw_label_lines(canvas, label, screen_position, true)
+    }
+
+    // Same as draw_label without caching the rendered lines, for text that changes
+    // most frames, eg the debug overlay's stats
+    pub fn draw_label_uncached(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        label: &TextLabelComponent,
+        screen_position: Vector2<f32>,
+    ) -> Result<()> {
+        self.draw_label_lines(canvas, label, screen_position, false)
+    }
+
+    fn draw_label_lines(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        label: &TextLabelComponent,
+        screen_position: Vector2<f32>,
+        is_cached: bool,
+    ) -> Result<()> {
+        let font = self.load_font(&label.font_id, label.font_size)?;
+        let measure = |s: &str| font.size_of(s).map_or(0, |(width, _)| width);
+        let lines = wrap_text(&label.text, label.wrap_width, measure);
+        let widths: Vec<u32> = lines.iter().map(|line| measure(line)).collect();
+        let line_height = font.recommended_line_spacing().max(font.height());
+        let block_width = label
+            .wrap_width
+            .unwrap_or_else(|| widths.iter().copied().max().unwrap_or(0));
+        let left = block_left(label.align, screen_position.x.round() as i32, block_width);
+        let top = screen_position.y.round() as i32;
+
+        for (index, (line, width)) in lines.iter().zip(widths).enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let x = left + align_offset(label.align, width, block_width);
+            let y = top + index as i32 * line_height;
+            if is_cached {
+                let cached = self.line_texture(&label.font_id, label.font_size, line)?;
+                copy_line(canvas, cached, label.color, x, y, line)?;
+            } else {
+                let mut rendered = self.render_line(&label.font_id, label.font_size, line)?;
+                let result = copy_line(canvas, &mut rendered, label.color, x, y, line);
+                // SAFETY: the window's renderer is alive while the game runs
+                unsafe { rendered.texture.destroy() };
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_line(&mut self, font_id: &str, size: u16, line: &str) -> Result<CachedLine> {
+        let surface = self
+            .load_font(font_id, size)?
+            .render(line)
+            .blended(Color::WHITE)
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Failed to render text {line:?}"))?;
+        let texture = self
+            .texture_creator
+            .create_texture_from_surface(&surface)
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("Failed to create texture for text {line:?}"))?;
+        Ok(CachedLine {
+            texture,
+            width: surface.width(),
+            height: surface.height(),
+            last_used: self.frame,
+        })
+    }
+
+    fn line_texture(&mut self, font_id: &str, size: u16, line: &str) -> Result<&mut CachedLine> {
+        let key = (font_id.to_string(), size, line.to_string());
+        if !self.lines.contains_key(&key) {
+            let cached = self.render_line(font_id, size, line)?;
+            self.lines.insert(key.clone(), cached);
+        }
+        let cached = self.lines.get_mut(&key).expect("line was just cached");
+        cached.last_used = self.frame;
+        Ok(cached)
+    }
+
+    // rendered lines cached right now
+    pub fn cached_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    // Drops lines that weren't drawn for a while, call once per frame after present
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.remove_lines(|_, cached| cached.last_used + TEXT_CACHE_FRAMES < frame);
+    }
+
+    // Textures aren't freed on drop, see the sdl2 unsafe_textures feature
+    fn remove_lines(&mut self, mut should_remove: impl FnMut(&LineKey, &CachedLine) -> bool) {
+        let removed: Vec<LineKey> = self
+            .lines
+            .iter()
+            .filter(|(key, cached)| should_remove(key, cached))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in removed {
+            if let Some(cached) = self.lines.remove(&key) {
+                // SAFETY: the window's renderer is alive while the game runs
+                unsafe { cached.texture.destroy() };
+            }
+        }
+    }
+}
+
+// Tints the white line texture and draws it with its top left at (x, y)
+fn copy_line(
+    canvas: &mut Canvas<Window>,
+    rendered: &mut CachedLine,
+    color: Color,
+    x: i32,
+    y: i32,
+    line: &str,
+) -> Result<()> {
+    rendered.texture.set_color_mod(color.r, color.g, color.b);
+    rendered.texture.set_alpha_mod(color.a);
+    let dst = Rect::new(x, y, rendered.width, rendered.height);
+    canvas
+        .copy(&rendered.texture, None, dst)
+        .map_err(|e| anyhow!("{}", e))
+        .with_context(|| format!("Failed to draw text {line:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one unit per character
+    fn wrap(text: &str, wrap_width: Option<u32>) -> Vec<String> {
+        wrap_text(text, wrap_width, |s| s.chars().count() as u32)
+    }
+
+    #[test]
+    fn newlines_always_break() {
+        assert_eq!(wrap("ab\ncd", None), ["ab", "cd"]);
+        assert_eq!(wrap("ab\n", None), ["ab", ""]);
+        assert_eq!(wrap("", None), [""]);
+        assert_eq!(wrap("ab cd\nef", Some(2)), ["ab", "cd", "ef"]);
+        assert_eq!(wrap("ab\n\ncd", Some(10)), ["ab", "", "cd"]);
+    }
+
+    #[test]
+    fn lines_wrap_at_spaces() {
+        assert_eq!(
+            wrap("the quick brown fox", Some(10)),
+            ["the quick", "brown fox"]
+        );
+        assert_eq!(wrap("the quick", Some(9)), ["the quick"]);
+    }
+
+    #[test]
+    fn words_wider_than_the_width_break_anywhere() {
+        assert_eq!(wrap("abcdefghij", Some(4)), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("hi abcdefg", Some(4)), ["hi", "abcd", "efg"]);
+        // at least one character per line, even if it doesn't fit
+        assert_eq!(wrap("ab", Some(0)), ["a", "b"]);
+    }
+
+    #[test]
+    fn repeated_spaces_collapse_when_wrapping() {
+        assert_eq!(wrap("a   b", Some(10)), ["a b"]);
+        assert_eq!(wrap("  a b  ", Some(10)), ["a b"]);
+        assert_eq!(wrap("a   b", None), ["a   b"]);
+    }
+
+    #[test]
+    fn lines_align_within_the_block() {
+        assert_eq!(align_offset(TextAlign::Left, 10, 20), 0);
+        assert_eq!(align_offset(TextAlign::Center, 10, 20), 5);
+        assert_eq!(align_offset(TextAlign::Right, 10, 20), 10);
+        assert_eq!(align_offset(TextAlign::Right, 30, 20), 0);
+    }
+
+    #[test]
+    fn block_is_placed_around_the_anchor() {
+        assert_eq!(block_left(TextAlign::Left, 100, 40), 100);
+        assert_eq!(block_left(TextAlign::Center, 100, 40), 80);
+        assert_eq!(block_left(TextAlign::Right, 100, 40), 60);
+    }
+}